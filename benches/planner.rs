@@ -0,0 +1,82 @@
+//! Benchmarks of the click planner on the red detections of hard.png
+//!
+//! ``` cargo bench --bench planner ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use iron_dome::generator::gen_clicks_from_targets_rand;
+use iron_dome::screen::BoxGrid;
+use iron_dome::{detect, BoundingBox, Coord, DetectOptions, PlannerConfig, Target};
+
+fn hard_detections() -> (Vec<BoundingBox>, u32, u32, u32) {
+    let img = image::open("hard.png").unwrap().to_rgb8();
+    let options = DetectOptions {
+        scan_debug: false,
+        scan_red: true,
+        ..DetectOptions::default()
+    };
+    let detections = detect(&img, options);
+    (
+        detections.bbs,
+        detections.remote_radius,
+        img.width(),
+        img.height(),
+    )
+}
+
+/// A click on the corner of every bb, where each query finds a few neighbours
+fn corner_clicks(bbs: &[BoundingBox]) -> Vec<Coord> {
+    bbs.iter().map(|bb| bb.left_top).collect()
+}
+
+fn collision_counting(c: &mut Criterion) {
+    let (bbs, remote_radius, _, _) = hard_detections();
+    let clicks = corner_clicks(&bbs);
+    let grid = BoxGrid::new(&bbs, 2 * remote_radius as i64 + 1);
+
+    let mut group = c.benchmark_group("count collisions on hard.png");
+    group.bench_function("linear scan", |b| {
+        b.iter(|| {
+            clicks
+                .iter()
+                .map(|click| {
+                    bbs.iter()
+                        .filter(|bb| bb.collides_with_circle(*click, remote_radius))
+                        .count()
+                })
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("grid", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for click in &clicks {
+                grid.query_circle(*click, remote_radius, |_| count += 1);
+            }
+            count
+        })
+    });
+    group.bench_function("build grid", |b| {
+        b.iter(|| BoxGrid::new(&bbs, 2 * remote_radius as i64 + 1))
+    });
+    group.finish();
+}
+
+fn random_generator(c: &mut Criterion) {
+    let (bbs, remote_radius, w, h) = hard_detections();
+    let targets = bbs.into_iter().map(Target::unknown).collect::<Vec<_>>();
+    let config = PlannerConfig {
+        guesses: 1,
+        samples: 100,
+        ..PlannerConfig::default()
+    };
+
+    let mut group = c.benchmark_group("random generator on hard.png");
+    group.sample_size(10);
+    group.bench_function("1 guess, 100 samples", |b| {
+        b.iter(|| gen_clicks_from_targets_rand(&targets, remote_radius, w, h, config))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, collision_counting, random_generator);
+criterion_main!(benches);
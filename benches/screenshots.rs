@@ -0,0 +1,98 @@
+//! Benchmarks of each stage of the pipeline on every bundled screenshot
+//!
+//! ``` cargo bench --bench screenshots ```
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use image::RgbImage;
+use iron_dome::color::ColorClassifier;
+use iron_dome::components::process_red_components;
+use iron_dome::debug::{find_spawner_positions, find_worm_positions, remap_positions_to_bb};
+use iron_dome::generator::{gen_clicks_from_targets_fixed, gen_clicks_from_targets_rand};
+use iron_dome::legit::process_red;
+use iron_dome::solver::{solve, SolverOptions};
+use iron_dome::{detect, BoundingBox, Coord, DetectOptions, HitRequirements, PlannerConfig};
+
+/// hard.png and zoom/z1.png to zoom/z10.png, with their file names
+fn screenshots() -> Vec<(String, RgbImage)> {
+    let mut paths = vec!["hard.png".to_owned()];
+    paths.extend((1..=10).map(|i| format!("zoom/z{}.png", i)));
+    paths
+        .into_iter()
+        .map(|path| {
+            let img = image::open(&path).unwrap().to_rgb8();
+            (path, img)
+        })
+        .collect()
+}
+
+fn detectors(c: &mut Criterion) {
+    let screenshots = screenshots();
+    // The same mask that `detect` uses for spawners
+    let spawner_mask = BoundingBox {
+        left_top: Coord { w: -30, h: -11 },
+        right_bottom: Coord { w: 23, h: 31 },
+    };
+
+    let mut group = c.benchmark_group("detectors");
+    group.sample_size(10);
+    for (name, img) in &screenshots {
+        group.bench_with_input(BenchmarkId::new("process_red", name), img, |b, img| {
+            b.iter_batched(
+                || img.clone(),
+                |mut img| process_red(&mut img, &ColorClassifier::default()),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(
+            BenchmarkId::new("process_red_components", name),
+            img,
+            |b, img| b.iter(|| process_red_components(img, &ColorClassifier::default())),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("find_debug_positions", name),
+            img,
+            |b, img| b.iter(|| (find_spawner_positions(img), find_worm_positions(img))),
+        );
+        let positions = find_spawner_positions(img);
+        group.bench_with_input(
+            BenchmarkId::new("remap_positions_to_bb", name),
+            img,
+            |b, img| {
+                b.iter_batched(
+                    || img.clone(),
+                    |mut img| remap_positions_to_bb(&positions, &spawner_mask, &mut img),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn generators(c: &mut Criterion) {
+    let screenshots = screenshots();
+
+    let mut group = c.benchmark_group("generators");
+    group.sample_size(10);
+    for (name, img) in &screenshots {
+        let detections = detect(img, DetectOptions::default());
+        let targets = detections.targets(&HitRequirements::default());
+        let r = detections.remote_radius;
+        let (w, h) = img.dimensions();
+        group.bench_with_input(BenchmarkId::new("random", name), &targets, |b, targets| {
+            b.iter(|| gen_clicks_from_targets_rand(targets, r, w, h, PlannerConfig::default()))
+        });
+        group.bench_with_input(BenchmarkId::new("fixed", name), &targets, |b, targets| {
+            b.iter(|| gen_clicks_from_targets_fixed(targets, r, w, h))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("exact", name),
+            &detections.bbs,
+            |b, bbs| b.iter(|| solve(bbs, r, w, h, SolverOptions::default())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, detectors, generators);
+criterion_main!(benches);
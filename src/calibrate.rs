@@ -0,0 +1,168 @@
+//! GUI mode to fit the enemy colour classifier to pixels picked from a screenshot
+
+use egui::{Color32, Pos2, Sense, Stroke};
+use image::{Rgb, RgbImage};
+
+use iron_dome::color::{fit, ColorClassifier};
+use iron_dome::config::Config;
+use iron_dome::draw::classifier_preview;
+
+/// Radius in points of the marker drawn on each picked pixel
+const MARKER_RADIUS: f32 = 4.;
+
+#[derive(Default)]
+pub struct ColorCalibrator {
+    img: Option<RgbImage>,
+    texture: Option<egui::TextureHandle>,
+    /// Which pixels the fitted classifier sees as enemies
+    preview: Option<egui::TextureHandle>,
+    show_preview: bool,
+    /// Screenshot pixels picked from enemies
+    enemy: Vec<[u32; 2]>,
+    /// Screenshot pixels picked from anything that isn't an enemy
+    background: Vec<[u32; 2]>,
+    fitted: Option<ColorClassifier>,
+    status: String,
+}
+
+impl ColorCalibrator {
+    /// Pick pixels from img, forgetting the pixels picked from the previous image
+    pub fn set_image(&mut self, ctx: &egui::Context, img: &RgbImage) {
+        self.texture = Some(load_texture(ctx, "screenshot", img));
+        self.img = Some(img.clone());
+        self.enemy.clear();
+        self.background.clear();
+        self.preview = None;
+        self.fitted = None;
+    }
+
+    pub fn has_image(&self) -> bool {
+        self.img.is_some()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, config: &mut Config, config_path: &str) {
+        ui.horizontal(|ui| {
+            let fitted = self.fitted.clone();
+            if ui
+                .add_enabled(fitted.is_some(), egui::Button::new("Save"))
+                .clicked()
+            {
+                config.set_classifier(fitted.unwrap());
+                self.status = match config.save(config_path) {
+                    Ok(()) => format!(
+                        "Saved to profile {} in {}",
+                        config.color_profile, config_path
+                    ),
+                    Err(e) => format!("Failed to save {}: {}", config_path, e),
+                };
+            }
+            if ui.button("Clear").clicked() {
+                self.enemy.clear();
+                self.background.clear();
+                self.preview = None;
+                self.fitted = None;
+            }
+            ui.checkbox(&mut self.show_preview, "Preview");
+            ui.label(&self.status);
+        });
+        ui.label(format!(
+            "Click enemy pixels and right click background pixels. Profile: {}",
+            config.color_profile
+        ));
+
+        let (img, texture) = match (&self.img, &self.texture) {
+            (Some(img), Some(texture)) => (img, texture),
+            _ => return,
+        };
+        let texture = match (&self.preview, self.show_preview) {
+            (Some(preview), true) => preview,
+            _ => texture,
+        };
+        let (img_w, img_h) = img.dimensions();
+        let avail = ui.available_size();
+        let fit_scale = f32::min(avail.x / img_w as f32, avail.y / img_h as f32);
+        let size = egui::vec2(img_w as f32 * fit_scale, img_h as f32 * fit_scale);
+        let response = ui.add(egui::Image::new(texture.id(), size).sense(Sense::click()));
+        let rect = response.rect;
+        let to_pixel = |pos: Pos2| {
+            let rel = (pos - rect.min) / rect.size();
+            [
+                ((rel.x * img_w as f32) as u32).min(img_w - 1),
+                ((rel.y * img_h as f32) as u32).min(img_h - 1),
+            ]
+        };
+        let from_pixel = |p: [u32; 2]| {
+            rect.min
+                + egui::vec2(
+                    (p[0] as f32 + 0.5) / img_w as f32,
+                    (p[1] as f32 + 0.5) / img_h as f32,
+                ) * rect.size()
+        };
+
+        let painter = ui.painter_at(rect);
+        for (pixels, color) in [
+            (&self.enemy, Color32::GREEN),
+            (&self.background, Color32::WHITE),
+        ] {
+            for p in pixels.iter() {
+                painter.circle_stroke(from_pixel(*p), MARKER_RADIUS, Stroke::new(2., color));
+            }
+        }
+
+        let picked = response.interact_pointer_pos().map(to_pixel);
+        let changed = match picked {
+            Some(p) if response.clicked() => {
+                self.enemy.push(p);
+                true
+            }
+            Some(p) if response.secondary_clicked() => {
+                self.background.push(p);
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            self.refit(ui.ctx());
+        }
+    }
+
+    /// Fit the classifier to the picked pixels and redraw the preview
+    fn refit(&mut self, ctx: &egui::Context) {
+        let img = match &self.img {
+            Some(img) => img,
+            None => return,
+        };
+        let pixels = |picked: &[[u32; 2]]| -> Vec<Rgb<u8>> {
+            picked.iter().map(|p| *img.get_pixel(p[0], p[1])).collect()
+        };
+        match fit(&pixels(&self.enemy), &pixels(&self.background)) {
+            Ok(classifier) => {
+                self.preview = Some(load_texture(
+                    ctx,
+                    "preview",
+                    &classifier_preview(img, &classifier),
+                ));
+                self.status = format!("{:?}", classifier);
+                self.fitted = Some(classifier);
+            }
+            Err(e) => {
+                self.preview = None;
+                self.fitted = None;
+                self.status = e;
+            }
+        }
+    }
+}
+
+fn load_texture(ctx: &egui::Context, name: &str, img: &RgbImage) -> egui::TextureHandle {
+    let pixels = img
+        .pixels()
+        .map(|px| Color32::from_rgb(px[0], px[1], px[2]))
+        .collect();
+    let color_image = egui::ColorImage {
+        size: [img.width() as usize, img.height() as usize],
+        pixels,
+    };
+    // Nearest so the preview shows exactly which pixels look like enemies
+    ctx.load_texture(name, color_image, egui::TextureFilter::Nearest)
+}
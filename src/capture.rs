@@ -0,0 +1,527 @@
+//! Module for the backends that provide screenshots of the game
+
+use captrs::Capturer;
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::screen::Coord;
+
+/// A screenshot, and where it was taken from
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub img: RgbImage,
+    /// Desktop position of the top left pixel of img. Add to a click in img to get a desktop click
+    pub origin: Coord,
+    /// Width and height of the whole desktop that origin is relative to
+    pub desktop_size: (u32, u32),
+}
+
+impl Frame {
+    /// A frame that covers the whole desktop
+    pub fn new(img: RgbImage) -> Self {
+        Frame {
+            desktop_size: img.dimensions(),
+            img,
+            origin: Coord::default(),
+        }
+    }
+
+    /// Convert clicks on the frame into clicks on the desktop
+    pub fn to_desktop(&self, clicks: &mut [Coord]) {
+        for click in clicks {
+            click.w += self.origin.w;
+            click.h += self.origin.h;
+        }
+    }
+}
+
+/// Something that can provide screenshots
+pub trait FrameSource {
+    /// Take the next screenshot. Returns None when the source has run out of frames
+    fn next_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>>;
+}
+
+/// Which `FrameSource` to use, selectable from the config file or command line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureBackend {
+    /// Capture a whole display with captrs
+    Captrs(usize),
+    /// Capture a single X11 window by id
+    X11Window(u64),
+    /// Capture the first X11 window whose WM_CLASS, or failing that title, contains the name
+    FindWindow(String),
+    /// Replay the PNGs in a directory, in file name order
+    Replay(PathBuf),
+    /// Read raw 8 bit RGB frames of a fixed size from a file or named pipe, or stdin for "-"
+    Raw {
+        width: u32,
+        height: u32,
+        path: PathBuf,
+    },
+}
+
+impl Default for CaptureBackend {
+    fn default() -> Self {
+        CaptureBackend::FindWindow("factorio".to_owned())
+    }
+}
+
+impl FromStr for CaptureBackend {
+    type Err = String;
+
+    /// Parses captrs[:<display>], x11:<window id>, window:<name>, replay:<dir> or raw:<w>x<h>:<path>
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        let bad = || format!("Invalid frame source {}", s);
+        match (kind, arg) {
+            ("captrs", None) => Ok(CaptureBackend::Captrs(0)),
+            ("captrs", Some(display)) => Ok(CaptureBackend::Captrs(
+                display.parse().map_err(|_| bad())?,
+            )),
+            ("x11", Some(id)) => Ok(CaptureBackend::X11Window(parse_window_id(id).ok_or_else(bad)?)),
+            ("window", Some(name)) => Ok(CaptureBackend::FindWindow(name.to_owned())),
+            ("replay", Some(dir)) => Ok(CaptureBackend::Replay(dir.into())),
+            ("raw", Some(arg)) => {
+                let (size, path) = arg.split_once(':').ok_or_else(bad)?;
+                let (width, height) = size.split_once('x').ok_or_else(bad)?;
+                Ok(CaptureBackend::Raw {
+                    width: width.parse().map_err(|_| bad())?,
+                    height: height.parse().map_err(|_| bad())?,
+                    path: path.into(),
+                })
+            }
+            _ => Err(format!(
+                "{}, expected captrs[:<display>], x11:<window id>, window:<name>, replay:<dir> or raw:<w>x<h>:<path>",
+                bad()
+            )),
+        }
+    }
+}
+
+/// Window ids are usually written in hex, eg 0x3a00007
+fn parse_window_id(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+impl CaptureBackend {
+    /// Create the source for this backend
+    pub fn open(&self) -> Result<Box<dyn FrameSource>, Box<dyn Error>> {
+        Ok(match self {
+            CaptureBackend::Captrs(display) => Box::new(CaptrsSource::open(*display)?),
+            CaptureBackend::X11Window(window) => Box::new(X11WindowSource::open(*window)?),
+            CaptureBackend::FindWindow(name) => Box::new(X11WindowSource::find(name)?),
+            CaptureBackend::Replay(dir) => Box::new(ReplaySource::open(dir)?),
+            CaptureBackend::Raw {
+                width,
+                height,
+                path,
+            } => Box::new(RawSource::open(path, *width, *height)?),
+        })
+    }
+}
+
+pub struct CaptrsSource {
+    capturer: Capturer,
+    /// Only used to measure the desktop, which captrs can't
+    display: *mut x11::xlib::Display,
+}
+
+impl CaptrsSource {
+    pub fn open(display: usize) -> Result<Self, Box<dyn Error>> {
+        Ok(CaptrsSource {
+            capturer: Capturer::new(display)?,
+            display: open_display()?,
+        })
+    }
+}
+
+impl FrameSource for CaptrsSource {
+    fn next_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>> {
+        let bytes = self
+            .capturer
+            .capture_frame()
+            .map_err(|e| format!("Failed to capture frame: {:?}", e))?;
+        let bytes = bytes
+            .into_iter()
+            .flat_map(|bgr| [bgr.r, bgr.g, bgr.b])
+            .collect::<Vec<u8>>();
+        let geometry = self.capturer.geometry();
+        let position = self.capturer.position();
+
+        let img = RgbImage::from_raw(geometry.0, geometry.1, bytes)
+            .ok_or("Captured frame does not match the display size")?;
+        // The display is one monitor of the desktop, and clicks are mapped onto the whole desktop
+        let (desktop_w, desktop_h) = unsafe { desktop_size(self.display)? };
+        Ok(Some(Frame {
+            img,
+            origin: Coord {
+                w: position.0 as i64,
+                h: position.1 as i64,
+            },
+            desktop_size: (desktop_w as u32, desktop_h as u32),
+        }))
+    }
+}
+
+impl Drop for CaptrsSource {
+    fn drop(&mut self) {
+        unsafe {
+            x11::xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+/// Captures the client area of an X11 window, cropped to the part that is on the desktop.
+/// The desktop is captured rather than the window itself, so anything on top of the window is
+/// captured too, as the game would see it.
+pub struct X11WindowSource {
+    display: *mut x11::xlib::Display,
+    window: Option<x11::xlib::Window>,
+    /// Lowercase name to search for when window is not known yet, or has been closed
+    name: Option<String>,
+}
+
+/// Xlib exits the process on errors by default, eg when a window is closed while being captured.
+/// Failed calls are already checked by their return values
+unsafe extern "C" fn ignore_x_error(
+    _display: *mut x11::xlib::Display,
+    _event: *mut x11::xlib::XErrorEvent,
+) -> libc::c_int {
+    0
+}
+
+/// Run f with X errors ignored, and put the previous error handler back afterwards.
+/// The error handler is shared by the whole process, so it is only replaced while the calls of f
+/// are in flight
+unsafe fn ignoring_x_errors<T>(display: *mut x11::xlib::Display, f: impl FnOnce() -> T) -> T {
+    let old_handler = x11::xlib::XSetErrorHandler(Some(ignore_x_error));
+    let result = f();
+    // Errors are only reported once the server has processed the calls
+    x11::xlib::XSync(display, x11::xlib::False);
+    x11::xlib::XSetErrorHandler(old_handler);
+    result
+}
+
+fn open_display() -> Result<*mut x11::xlib::Display, Box<dyn Error>> {
+    let display = unsafe { x11::xlib::XOpenDisplay(std::ptr::null()) };
+    if display.is_null() {
+        return Err("Could not open the X display".into());
+    }
+    Ok(display)
+}
+
+/// Width and height of the root window, which spans every monitor of the desktop
+unsafe fn desktop_size(display: *mut x11::xlib::Display) -> Result<(i32, i32), Box<dyn Error>> {
+    let root = x11::xlib::XDefaultRootWindow(display);
+    let mut root_attrs: x11::xlib::XWindowAttributes = std::mem::zeroed();
+    if x11::xlib::XGetWindowAttributes(display, root, &mut root_attrs) == 0 {
+        return Err("Could not get the size of the desktop".into());
+    }
+    Ok((root_attrs.width, root_attrs.height))
+}
+
+impl X11WindowSource {
+    pub fn open(window: u64) -> Result<Self, Box<dyn Error>> {
+        Ok(X11WindowSource {
+            display: open_display()?,
+            window: Some(window as x11::xlib::Window),
+            name: None,
+        })
+    }
+
+    /// Capture the first window with name in its WM_CLASS, or failing that its title
+    pub fn find(name: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(X11WindowSource {
+            display: open_display()?,
+            window: None,
+            name: Some(name.to_lowercase()),
+        })
+    }
+
+    fn window(&mut self) -> Result<x11::xlib::Window, Box<dyn Error>> {
+        if let Some(window) = self.window {
+            return Ok(window);
+        }
+        let name = self.name.as_deref().unwrap_or_default();
+        let display = self.display;
+        let window = unsafe {
+            ignoring_x_errors(display, || {
+                let root = x11::xlib::XDefaultRootWindow(display);
+                find_window(display, root, name, WindowProperty::Class)
+                    .or_else(|| find_window(display, root, name, WindowProperty::Title))
+            })
+        }
+        .ok_or_else(|| format!("Could not find a window named {}", name))?;
+        self.window = Some(window);
+        Ok(window)
+    }
+
+    /// Copy the part of window that is on the desktop. Xlib errors must be ignored while this
+    /// runs, see ignoring_x_errors
+    unsafe fn capture(
+        &mut self,
+        window: x11::xlib::Window,
+    ) -> Result<Option<Frame>, Box<dyn Error>> {
+        use x11::xlib;
+        let mut attrs: xlib::XWindowAttributes = std::mem::zeroed();
+        if xlib::XGetWindowAttributes(self.display, window, &mut attrs) == 0 {
+            if self.name.is_some() {
+                // Closed, search again next frame
+                self.window = None;
+            }
+            return Err(format!("Could not get attributes of window {:#x}", window).into());
+        }
+        if attrs.map_state != xlib::IsViewable {
+            return Err(format!("Window {:#x} is not visible", window).into());
+        }
+
+        // Where the window is on the desktop
+        let root = xlib::XDefaultRootWindow(self.display);
+        let (desktop_w, desktop_h) = desktop_size(self.display)?;
+        let (mut x, mut y, mut child) = (0, 0, 0);
+        if xlib::XTranslateCoordinates(self.display, window, root, 0, 0, &mut x, &mut y, &mut child)
+            == 0
+        {
+            return Err(format!("Window {:#x} is not on the default screen", window).into());
+        }
+        // XGetImage fails if any of the rectangle is off the desktop
+        let (left, top) = (x.max(0), y.max(0));
+        let right = (x + attrs.width).min(desktop_w);
+        let bottom = (y + attrs.height).min(desktop_h);
+        if right <= left || bottom <= top {
+            return Err(format!("Window {:#x} is off the desktop", window).into());
+        }
+        let ximage = xlib::XGetImage(
+            self.display,
+            root,
+            left,
+            top,
+            (right - left) as u32,
+            (bottom - top) as u32,
+            !0,
+            xlib::ZPixmap,
+        );
+        if ximage.is_null() {
+            return Err(format!("Could not capture window {:#x}", window).into());
+        }
+        let img = ximage_to_rgb(&*ximage);
+        xlib::XDestroyImage(ximage);
+        Ok(Some(Frame {
+            img: img?,
+            origin: Coord {
+                w: left as i64,
+                h: top as i64,
+            },
+            desktop_size: (desktop_w as u32, desktop_h as u32),
+        }))
+    }
+}
+
+impl FrameSource for X11WindowSource {
+    fn next_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>> {
+        let window = self.window()?;
+        let display = self.display;
+        unsafe { ignoring_x_errors(display, || self.capture(window)) }
+    }
+}
+
+impl Drop for X11WindowSource {
+    fn drop(&mut self) {
+        unsafe {
+            x11::xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum WindowProperty {
+    Class,
+    Title,
+}
+
+/// Depth first search for a visible window with name in the property, topmost windows first
+unsafe fn find_window(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    name: &str,
+    property: WindowProperty,
+) -> Option<x11::xlib::Window> {
+    use x11::xlib;
+    if window_matches(display, window, name, property) {
+        return Some(window);
+    }
+    let (mut root, mut parent) = (0, 0);
+    let mut children = std::ptr::null_mut();
+    let mut n = 0;
+    if xlib::XQueryTree(
+        display,
+        window,
+        &mut root,
+        &mut parent,
+        &mut children,
+        &mut n,
+    ) == 0
+        || children.is_null()
+    {
+        return None;
+    }
+    // Children are listed bottom to top
+    let found = std::slice::from_raw_parts(children, n as usize)
+        .iter()
+        .rev()
+        .find_map(|&child| find_window(display, child, name, property));
+    xlib::XFree(children as *mut _);
+    found
+}
+
+unsafe fn window_matches(
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+    name: &str,
+    property: WindowProperty,
+) -> bool {
+    use std::ffi::CStr;
+    use x11::xlib;
+    let mut attrs: xlib::XWindowAttributes = std::mem::zeroed();
+    if xlib::XGetWindowAttributes(display, window, &mut attrs) == 0
+        || attrs.map_state != xlib::IsViewable
+    {
+        return false;
+    }
+    let mut strings = vec![];
+    match property {
+        WindowProperty::Class => {
+            let mut hint: xlib::XClassHint = std::mem::zeroed();
+            if xlib::XGetClassHint(display, window, &mut hint) != 0 {
+                strings.push(hint.res_name);
+                strings.push(hint.res_class);
+            }
+        }
+        WindowProperty::Title => {
+            let mut title = std::ptr::null_mut();
+            if xlib::XFetchName(display, window, &mut title) != 0 {
+                strings.push(title);
+            }
+        }
+    }
+    let mut matches = false;
+    for s in strings.into_iter().filter(|s| !s.is_null()) {
+        matches |= CStr::from_ptr(s)
+            .to_string_lossy()
+            .to_lowercase()
+            .contains(name);
+        xlib::XFree(s as *mut _);
+    }
+    matches
+}
+
+/// Copy a 24/32 bit TrueColor XImage into an RgbImage
+fn ximage_to_rgb(ximage: &x11::xlib::XImage) -> Result<RgbImage, Box<dyn Error>> {
+    if ximage.bits_per_pixel != 32 {
+        return Err(format!(
+            "Unsupported {} bits per pixel window",
+            ximage.bits_per_pixel
+        )
+        .into());
+    }
+    let (width, height) = (ximage.width as u32, ximage.height as u32);
+    let data = unsafe {
+        std::slice::from_raw_parts(
+            ximage.data as *const u8,
+            ximage.bytes_per_line as usize * height as usize,
+        )
+    };
+    let channel = |px: u32, mask: u64| ((px as u64 & mask) >> mask.trailing_zeros()) as u8;
+    let mut img = RgbImage::new(width, height);
+    for (w, h, px) in img.enumerate_pixels_mut() {
+        let i = h as usize * ximage.bytes_per_line as usize + w as usize * 4;
+        let bytes = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+        let value = if ximage.byte_order == x11::xlib::LSBFirst {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        };
+        *px = image::Rgb([
+            channel(value, ximage.red_mask),
+            channel(value, ximage.green_mask),
+            channel(value, ximage.blue_mask),
+        ]);
+    }
+    Ok(img)
+}
+
+/// Replays the PNG screenshots in a directory, in file name order
+pub struct ReplaySource {
+    paths: std::vec::IntoIter<PathBuf>,
+}
+
+impl ReplaySource {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|p| p.extension().is_some_and(|ext| ext == "png"));
+        paths.sort();
+        Ok(ReplaySource {
+            paths: paths.into_iter(),
+        })
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn next_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>> {
+        match self.paths.next() {
+            Some(path) => Ok(Some(Frame::new(image::open(path)?.into_rgb8()))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads raw frames of width * height * 3 bytes, with no header
+pub struct RawSource {
+    reader: Box<dyn Read>,
+    width: u32,
+    height: u32,
+}
+
+impl RawSource {
+    /// path: a file or named pipe, or "-" for stdin
+    pub fn open(path: impl AsRef<Path>, width: u32, height: u32) -> Result<Self, Box<dyn Error>> {
+        let reader: Box<dyn Read> = if path.as_ref() == Path::new("-") {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(File::open(path)?)
+        };
+        Ok(RawSource {
+            reader,
+            width,
+            height,
+        })
+    }
+}
+
+impl FrameSource for RawSource {
+    fn next_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>> {
+        let mut bytes = vec![0; self.width as usize * self.height as usize * 3];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let img = RgbImage::from_raw(self.width, self.height, bytes)
+            .ok_or("Raw frame does not match the frame size")?;
+        Ok(Some(Frame::new(img)))
+    }
+}
@@ -0,0 +1,130 @@
+//! Module to decide which pixel colours are enemies on the map view
+
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+/// Tells the red pixels of spawners and worms apart from everything else on the map.
+/// Game versions, mods and gamma settings change the colour of enemies, so it is set in the
+/// config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ColorClassifier {
+    /// Each channel within an inclusive range
+    RgbBox { min: [u8; 3], max: [u8; 3] },
+    /// Hue, saturation and value within inclusive ranges.
+    /// Hue is in degrees, and wraps around when the first is more than the second, eg
+    /// [340, 20] for reds. Saturation and value are from 0 to 1.
+    Hsv {
+        hue: [f64; 2],
+        saturation: [f64; 2],
+        value: [f64; 2],
+    },
+    /// Within max_distance of any of the colours, measured in RGB
+    Palette {
+        colors: Vec<[u8; 3]>,
+        max_distance: f64,
+    },
+}
+
+impl Default for ColorClassifier {
+    /// The enemy red of the vanilla map view
+    fn default() -> Self {
+        ColorClassifier::RgbBox {
+            min: [151, 12, 15],
+            max: [255, 34, 35],
+        }
+    }
+}
+
+impl ColorClassifier {
+    pub fn looks_like_enemy(&self, px: &Rgb<u8>) -> bool {
+        match self {
+            ColorClassifier::RgbBox { min, max } => {
+                (0..3).all(|i| px[i] >= min[i] && px[i] <= max[i])
+            }
+            ColorClassifier::Hsv {
+                hue,
+                saturation,
+                value,
+            } => {
+                let (h, s, v) = to_hsv(px);
+                let hue_matches = if hue[0] <= hue[1] {
+                    h >= hue[0] && h <= hue[1]
+                } else {
+                    h >= hue[0] || h <= hue[1]
+                };
+                hue_matches
+                    && s >= saturation[0]
+                    && s <= saturation[1]
+                    && v >= value[0]
+                    && v <= value[1]
+            }
+            ColorClassifier::Palette {
+                colors,
+                max_distance,
+            } => colors.iter().any(|color| {
+                let distance_sq = (0..3)
+                    .map(|i| (px[i] as f64 - color[i] as f64).powi(2))
+                    .sum::<f64>();
+                distance_sq <= max_distance * max_distance
+            }),
+        }
+    }
+}
+
+/// Channels the fitted box may grow past the picked enemy pixels, to take in the enemy pixels
+/// that were not picked
+const FIT_MARGIN: u8 = 12;
+
+/// Fit an RGB box around pixels picked from enemies, as wide as it can be up to FIT_MARGIN
+/// without taking in any of the pixels picked from the background
+pub fn fit(enemy: &[Rgb<u8>], background: &[Rgb<u8>]) -> Result<ColorClassifier, String> {
+    if enemy.is_empty() {
+        return Err("Pick at least one enemy pixel".to_owned());
+    }
+    let mut min = [u8::MAX; 3];
+    let mut max = [u8::MIN; 3];
+    for px in enemy {
+        for i in 0..3 {
+            min[i] = min[i].min(px[i]);
+            max[i] = max[i].max(px[i]);
+        }
+    }
+    for margin in (0..=FIT_MARGIN).rev() {
+        let classifier = ColorClassifier::RgbBox {
+            min: min.map(|c| c.saturating_sub(margin)),
+            max: max.map(|c| c.saturating_add(margin)),
+        };
+        if !background.iter().any(|px| classifier.looks_like_enemy(px)) {
+            return Ok(classifier);
+        }
+    }
+    let tight = ColorClassifier::RgbBox { min, max };
+    let overlap = background
+        .iter()
+        .filter(|px| tight.looks_like_enemy(px))
+        .count();
+    Err(format!(
+        "{} of the background pixels are within the colours of the enemy pixels",
+        overlap
+    ))
+}
+
+/// Hue in degrees from 0 to 360, and saturation and value from 0 to 1
+pub fn to_hsv(px: &Rgb<u8>) -> (f64, f64, f64) {
+    let [r, g, b] = px.0.map(|c| c as f64 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0. {
+        0.
+    } else if max == r {
+        60. * ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / delta + 2.)
+    } else {
+        60. * ((r - g) / delta + 4.)
+    };
+    let saturation = if max == 0. { 0. } else { delta / max };
+    (hue, saturation, max)
+}
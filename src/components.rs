@@ -0,0 +1,477 @@
+//! Module for the connected component red detector
+//!
+//! Labels every enemy pixel once with union-find, measures each blob of touching enemy pixels,
+//! and splits the blobs into spawner and worm sized bounding boxes. Unlike `legit::process_red`
+//! it reads the screenshot once, never masks it, and tells spawners and worms apart.
+
+use image::{Rgb, RgbImage};
+
+use crate::color::ColorClassifier;
+use crate::legit::{deduce_spawner_size, Stage};
+use crate::screen::{BoundingBox, Coord};
+use crate::TargetClass;
+
+/// Blobs that fill at least this fraction of their bounding box are taken to be one rectangle
+const SOLID_FILL: f64 = 0.9;
+/// Pixels a blob may be wider or taller than a spawner and still be a single target
+const SIZE_TOLERANCE: i64 = 2;
+/// Pieces of a split blob covering less than this fraction of a spawner's pixels are merged into
+/// a neighbouring piece
+const MIN_PIECE: f64 = 0.2;
+/// Fraction of its box the least filled target covers. A blob split into more pieces than its
+/// pixels can fill this much of is split too finely
+const TARGET_FILL: f64 = 0.8;
+/// Boxes at least this fraction of a spawner's width are spawners, and narrower ones worms.
+/// Worms are about as tall as spawners, but square
+const SPAWNER_WIDTH: f64 = 0.85;
+/// Label of pixels that are not enemies
+const NO_BLOB: u32 = u32::MAX;
+
+/// Sides of a bounding box
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sides {
+    pub left: bool,
+    pub top: bool,
+    pub right: bool,
+    pub bottom: bool,
+}
+
+/// One connected blob of enemy pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blob {
+    pub bb: BoundingBox,
+    /// Enemy pixels in the blob
+    pub pixels: i64,
+    /// Sides of the bounding box where the blob touches pure black unexplored chunks or the
+    /// edge of the screenshot, so the target may carry on past them
+    pub clipped: Sides,
+}
+
+impl Blob {
+    /// Fraction of the bounding box covered by the blob
+    pub fn fill(&self) -> f64 {
+        self.pixels as f64 / self.bb.pixel_count() as f64
+    }
+}
+
+/// The blob of each pixel of a screenshot
+pub struct Labels {
+    pub blobs: Vec<Blob>,
+    width: u32,
+    height: u32,
+    /// Index into blobs of each pixel, row by row, or NO_BLOB
+    labels: Vec<u32>,
+}
+
+impl Labels {
+    /// Index of the blob the pixel is part of, if it is an enemy
+    pub fn blob_at(&self, w: i64, h: i64) -> Option<usize> {
+        if w < 0 || h < 0 || w >= self.width as i64 || h >= self.height as i64 {
+            return None;
+        }
+        let label = self.labels[(h as u32 * self.width + w as u32) as usize];
+        (label != NO_BLOB).then_some(label as usize)
+    }
+
+    /// The pixels of each blob whose left and top neighbours are not in the same blob, row by row
+    pub fn left_top_corners(&self) -> Vec<Vec<Coord>> {
+        let mut corners = vec![vec![]; self.blobs.len()];
+        for h in 0..self.height as i64 {
+            for w in 0..self.width as i64 {
+                if let Some(blob) = self.blob_at(w, h) {
+                    if self.blob_at(w - 1, h) != Some(blob) && self.blob_at(w, h - 1) != Some(blob)
+                    {
+                        corners[blob].push(Coord { w, h });
+                    }
+                }
+            }
+        }
+        corners
+    }
+}
+
+/// Union-find over pixel indices, with path halving and union by size
+struct DisjointSet {
+    parent: Vec<u32>,
+    size: Vec<u32>,
+}
+
+impl DisjointSet {
+    fn new(n: usize) -> Self {
+        DisjointSet {
+            parent: (0..n as u32).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut i: u32) -> u32 {
+        while self.parent[i as usize] != i {
+            let grandparent = self.parent[self.parent[i as usize] as usize];
+            self.parent[i as usize] = grandparent;
+            i = grandparent;
+        }
+        i
+    }
+
+    fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let (big, small) = if self.size[a as usize] >= self.size[b as usize] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[small as usize] = big;
+        self.size[big as usize] += self.size[small as usize];
+    }
+}
+
+/// Label the enemy pixels of img by the 4-connected blob they are part of, and measure each blob
+pub fn label(img: &RgbImage, classifier: &ColorClassifier) -> Labels {
+    let (width, height) = img.dimensions();
+    let enemy = img
+        .pixels()
+        .map(|px| classifier.looks_like_enemy(px))
+        .collect::<Vec<_>>();
+    let index = |w: u32, h: u32| (h * width + w) as usize;
+
+    let mut set = DisjointSet::new(enemy.len());
+    for h in 0..height {
+        for w in 0..width {
+            if !enemy[index(w, h)] {
+                continue;
+            }
+            if w > 0 && enemy[index(w - 1, h)] {
+                set.union(index(w, h) as u32, index(w - 1, h) as u32);
+            }
+            if h > 0 && enemy[index(w, h - 1)] {
+                set.union(index(w, h) as u32, index(w, h - 1) as u32);
+            }
+        }
+    }
+
+    let mut blob_of_root = vec![NO_BLOB; enemy.len()];
+    let mut labels = vec![NO_BLOB; enemy.len()];
+    let mut blobs: Vec<Blob> = vec![];
+    for h in 0..height {
+        for w in 0..width {
+            let i = index(w, h);
+            if !enemy[i] {
+                continue;
+            }
+            let root = set.find(i as u32) as usize;
+            if blob_of_root[root] == NO_BLOB {
+                blob_of_root[root] = blobs.len() as u32;
+                blobs.push(Blob {
+                    bb: BoundingBox::new(
+                        Coord {
+                            w: w as i64,
+                            h: h as i64,
+                        },
+                        0,
+                        0,
+                    ),
+                    pixels: 0,
+                    clipped: Sides::default(),
+                });
+            }
+            labels[i] = blob_of_root[root];
+            let blob = &mut blobs[blob_of_root[root] as usize];
+            blob.pixels += 1;
+            let bb = &mut blob.bb;
+            bb.left_top.w = bb.left_top.w.min(w as i64);
+            bb.right_bottom.w = bb.right_bottom.w.max(w as i64);
+            bb.right_bottom.h = bb.right_bottom.h.max(h as i64);
+        }
+    }
+
+    // Pixels past the edge of the screenshot are hidden the same as unexplored chunks
+    let hidden = |w: i64, h: i64| {
+        w < 0
+            || h < 0
+            || w >= width as i64
+            || h >= height as i64
+            || *img.get_pixel(w as u32, h as u32) == Rgb([0, 0, 0])
+    };
+    for h in 0..height as i64 {
+        for w in 0..width as i64 {
+            let label = labels[index(w as u32, h as u32)];
+            if label == NO_BLOB {
+                continue;
+            }
+            let blob = &mut blobs[label as usize];
+            let bb = blob.bb;
+            let clipped = &mut blob.clipped;
+            clipped.left |= w == bb.left_top.w && hidden(w - 1, h);
+            clipped.top |= h == bb.left_top.h && hidden(w, h - 1);
+            clipped.right |= w == bb.right_bottom.w && hidden(w + 1, h);
+            clipped.bottom |= h == bb.right_bottom.h && hidden(w, h + 1);
+        }
+    }
+
+    Labels {
+        blobs,
+        width,
+        height,
+        labels,
+    }
+}
+
+/// Tries to generate bounding boxes of enemies from a screenshot of the map view, the same as
+/// `legit::process_red`, by splitting connected blobs of enemy pixels into spawner sized boxes.
+/// Returns the bounding boxes, the class of each, and the deduced width of a spawner.
+pub fn process_red_components(
+    img: &RgbImage,
+    classifier: &ColorClassifier,
+) -> (Vec<BoundingBox>, Vec<TargetClass>, i64) {
+    let (stages, classes, spawner_width) = process_red_components_stages(img, classifier);
+    let bbs = stages.into_iter().last().map(|s| s.bbs).unwrap_or_default();
+    (bbs, classes, spawner_width)
+}
+
+/// The same as process_red_components, but also returns the bounding box of each blob as the
+/// first stage. The last stage holds the split boxes that survived the final filter, and the
+/// classes are of those boxes.
+pub fn process_red_components_stages(
+    img: &RgbImage,
+    classifier: &ColorClassifier,
+) -> (Vec<Stage>, Vec<TargetClass>, i64) {
+    let labels = label(img, classifier);
+    let corners = labels.left_top_corners();
+    // Spawners in a nest touch each other, but most still show their left top corner
+    let corner_rects = corners
+        .iter()
+        .enumerate()
+        .flat_map(|(blob, corners)| corners.iter().map(move |c| (blob, *c)))
+        .map(|(blob, corner)| corner_rect(&labels, blob, corner))
+        .collect::<Vec<_>>();
+    let template = deduce_spawner_size(&corner_rects);
+
+    let mut bbs = vec![];
+    for (i, blob) in labels.blobs.iter().enumerate() {
+        if template.area() == 0 {
+            bbs.push(blob.bb);
+        } else if blob.bb.w() <= template.w() + SIZE_TOLERANCE
+            && blob.bb.h() <= template.h() + SIZE_TOLERANCE
+        {
+            bbs.push(unclip(blob, &template));
+        } else {
+            bbs.extend(split_blob(&labels, i, &corners[i], &template));
+        }
+    }
+    bbs.retain(|bb| bb.area() as f64 > template.area() as f64 / 5.0);
+    let classes = bbs.iter().map(|bb| classify(bb, &template)).collect();
+    let stages = vec![
+        Stage {
+            name: "blobs",
+            bbs: labels.blobs.iter().map(|blob| blob.bb).collect(),
+        },
+        Stage {
+            name: "final_filter",
+            bbs,
+        },
+    ];
+    (stages, classes, template.w())
+}
+
+/// Whether a box is wide enough to be a spawner, or only a worm.
+/// Without a spawner size to compare with, the class is unknown
+pub fn classify(bb: &BoundingBox, template: &BoundingBox) -> TargetClass {
+    if template.area() == 0 {
+        TargetClass::Unknown
+    } else if bb.w() as f64 >= SPAWNER_WIDTH * template.w() as f64 {
+        TargetClass::Spawner
+    } else {
+        TargetClass::Worm
+    }
+}
+
+/// The rectangle of blob pixels with its left top corner at corner, as wide as the row of blob
+/// pixels from the corner and as tall as the rows below are at least that wide
+fn corner_rect(labels: &Labels, blob: usize, corner: Coord) -> BoundingBox {
+    let in_blob = |w: i64, h: i64| labels.blob_at(w, h) == Some(blob);
+    let mut w = 0;
+    while in_blob(corner.w + w + 1, corner.h) {
+        w += 1;
+    }
+    let mut h = 0;
+    while (0..=w).all(|i| in_blob(corner.w + i, corner.h + h + 1)) {
+        h += 1;
+    }
+    BoundingBox::new(corner, w, h)
+}
+
+/// Grow a blob that is a spawner cut off by fog or the edge of the screenshot back to the full
+/// size of a spawner, away from the side it is cut off on
+pub fn unclip(blob: &Blob, template: &BoundingBox) -> BoundingBox {
+    let mut bb = blob.bb;
+    let clipped = blob.clipped;
+    if blob.fill() < SOLID_FILL {
+        // Not a rectangle, so not part of a single spawner
+        return bb;
+    }
+    if bb.h() < template.h() && (bb.w() - template.w()).abs() <= 1 {
+        if clipped.bottom && !clipped.top {
+            bb.right_bottom.h = bb.left_top.h + template.h();
+        } else if clipped.top && !clipped.bottom {
+            bb.left_top.h = bb.right_bottom.h - template.h();
+        }
+    }
+    if bb.w() < template.w() && (bb.h() - template.h()).abs() <= 1 {
+        if clipped.right && !clipped.left {
+            bb.right_bottom.w = bb.left_top.w + template.w();
+        } else if clipped.left && !clipped.right {
+            bb.left_top.w = bb.right_bottom.w - template.w();
+        }
+    }
+    bb
+}
+
+/// Cover a blob bigger than a spawner with spawner sized boxes. Boxes are placed on the left top
+/// corners of the blob first, then on its right bottom corners, then on the first uncovered
+/// pixel row by row. Each box is shrunk to the pixels it covers, so worms next to spawners come
+/// out worm sized. Boxes covering too few pixels to be a target are then merged into their
+/// neighbours, as are the smallest boxes while the blob has too few pixels to fill them all.
+/// left_top_corners: the blob's entry of `Labels::left_top_corners`
+pub fn split_blob(
+    labels: &Labels,
+    blob: usize,
+    left_top_corners: &[Coord],
+    template: &BoundingBox,
+) -> Vec<BoundingBox> {
+    let mut cover = Cover::new(labels, blob);
+    let bb = cover.bb;
+    let in_blob = |w: i64, h: i64| labels.blob_at(w, h) == Some(blob);
+
+    let mut pieces = vec![];
+    for &corner in left_top_corners {
+        if !cover.is_covered(corner) {
+            pieces.extend(cover.take(BoundingBox::new(corner, template.w(), template.h())));
+        }
+    }
+    let right_bottom_corners = bb
+        .enumerate()
+        .filter(|&(w, h)| in_blob(w, h) && !in_blob(w + 1, h) && !in_blob(w, h + 1))
+        .map(|(w, h)| Coord { w, h })
+        .collect::<Vec<_>>();
+    for corner in right_bottom_corners {
+        if !cover.is_covered(corner) {
+            let left_top = Coord {
+                w: corner.w - template.w(),
+                h: corner.h - template.h(),
+            };
+            pieces.extend(cover.take(BoundingBox::new(left_top, template.w(), template.h())));
+        }
+    }
+    for (w, h) in bb.enumerate() {
+        let point = Coord { w, h };
+        if in_blob(w, h) && !cover.is_covered(point) {
+            pieces.extend(cover.take(BoundingBox::new(point, template.w(), template.h())));
+        }
+    }
+
+    // The fewest pixels the pieces would cover if each were a whole target
+    let needed = |pieces: &[(BoundingBox, i64)]| {
+        pieces
+            .iter()
+            .map(|(bb, _)| bb.pixel_count().min(template.pixel_count()) as f64 * TARGET_FILL)
+            .sum::<f64>()
+    };
+    let least = MIN_PIECE * template.pixel_count() as f64;
+    while pieces.len() > 1 {
+        let smallest = (0..pieces.len()).min_by_key(|&i| pieces[i].1).unwrap();
+        if pieces[smallest].1 as f64 >= least && needed(&pieces) <= labels.blobs[blob].pixels as f64
+        {
+            break;
+        }
+        let (bb, pixels) = pieces.remove(smallest);
+        let around = BoundingBox {
+            left_top: Coord {
+                w: bb.left_top.w - 1,
+                h: bb.left_top.h - 1,
+            },
+            right_bottom: Coord {
+                w: bb.right_bottom.w + 1,
+                h: bb.right_bottom.h + 1,
+            },
+        };
+        // Grow the touching piece that stays the smallest
+        let merged_size = |i: &usize| union(&bb, &pieces[*i].0).pixel_count();
+        let neighbour = (0..pieces.len())
+            .filter(|&i| around.collides_with_bb(&pieces[i].0))
+            .min_by_key(merged_size)
+            .or_else(|| (0..pieces.len()).min_by_key(merged_size))
+            .unwrap();
+        pieces[neighbour].0 = union(&bb, &pieces[neighbour].0);
+        pieces[neighbour].1 += pixels;
+    }
+    pieces.into_iter().map(|(bb, _)| bb).collect()
+}
+
+/// The smallest box holding both a and b
+fn union(a: &BoundingBox, b: &BoundingBox) -> BoundingBox {
+    BoundingBox {
+        left_top: Coord {
+            w: a.left_top.w.min(b.left_top.w),
+            h: a.left_top.h.min(b.left_top.h),
+        },
+        right_bottom: Coord {
+            w: a.right_bottom.w.max(b.right_bottom.w),
+            h: a.right_bottom.h.max(b.right_bottom.h),
+        },
+    }
+}
+
+/// The pixels of a blob that are already in a box
+struct Cover<'a> {
+    labels: &'a Labels,
+    blob: usize,
+    bb: BoundingBox,
+    covered: Vec<bool>,
+}
+
+impl<'a> Cover<'a> {
+    fn new(labels: &'a Labels, blob: usize) -> Self {
+        let bb = labels.blobs[blob].bb;
+        Cover {
+            labels,
+            blob,
+            bb,
+            covered: vec![false; bb.pixel_count() as usize],
+        }
+    }
+
+    fn index(&self, point: Coord) -> usize {
+        ((point.h - self.bb.left_top.h) * (self.bb.w() + 1) + (point.w - self.bb.left_top.w))
+            as usize
+    }
+
+    fn is_covered(&self, point: Coord) -> bool {
+        self.covered[self.index(point)]
+    }
+
+    /// Cover the uncovered blob pixels in candidate, and return the bounding box of them and how
+    /// many there are
+    fn take(&mut self, candidate: BoundingBox) -> Option<(BoundingBox, i64)> {
+        let mut taken: Option<(BoundingBox, i64)> = None;
+        for (w, h) in candidate.enumerate() {
+            let point = Coord { w, h };
+            if self.labels.blob_at(w, h) != Some(self.blob) || self.is_covered(point) {
+                continue;
+            }
+            let i = self.index(point);
+            self.covered[i] = true;
+            let pixel = BoundingBox {
+                left_top: point,
+                right_bottom: point,
+            };
+            taken = Some(match taken {
+                None => (pixel, 1),
+                Some((bb, pixels)) => (union(&bb, &pixel), pixels + 1),
+            });
+        }
+        taken
+    }
+}
@@ -0,0 +1,390 @@
+//! Module for user settings that are persisted between runs
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::capture::CaptureBackend;
+use crate::color::ColorClassifier;
+use crate::hotkey::Hotkey;
+use crate::input::ClickBackend;
+use crate::screen::Coord;
+use crate::{Generator, HitRequirements, PlannerConfig, RedDetector, SafetyMargin};
+
+/// Default location of the config file, relative to the working directory
+pub const DEFAULT_CONFIG_PATH: &str = "iron_dome.toml";
+
+/// Name of the colour profile that is always available, with the enemy red of the unmodded game
+pub const VANILLA_COLOR_PROFILE: &str = "vanilla";
+
+/// Screen height that the Factorio UI is laid out for at a UI scale of 1.0
+const BASE_UI_HEIGHT: u32 = 1080;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Factorio's GUI scale. Derived from the screen height when not set
+    pub ui_scale: Option<f64>,
+    /// Name of the entry of color_profiles the red detector uses
+    pub color_profile: String,
+    /// Algorithm the red detector uses
+    pub red_detector: RedDetector,
+    /// Areas of the screen that must never be clicked
+    pub exclusion_zones: Vec<ExclusionZone>,
+    /// How screenshots of the game are taken
+    pub capture_backend: CaptureBackend,
+    /// How clicks are sent to the game
+    pub click_backend: ClickBackend,
+    /// Timing of the gui's auto fire mode
+    pub auto_fire: AutoFire,
+    /// Key that shoots from anywhere while the gui is running, eg "ctrl+F9"
+    pub hotkey: Option<Hotkey>,
+    /// How clicks are planned from the detected targets
+    pub generator: Generator,
+    /// Try to save shells by removing, merging and moving the planned clicks
+    pub refine: bool,
+    /// Room left for shells that land away from where they were aimed
+    pub safety: SafetyMargin,
+    /// Shells needed to kill each class of target
+    pub hits: HitRequirements,
+    /// Seed, threads and time limit of the random generator
+    pub planner: PlannerConfig,
+    /// Named classifiers of enemy pixels, for mods and graphics settings that change the colour
+    /// of enemies on the map. "vanilla" is the built in classifier unless it is overridden here.
+    pub color_profiles: BTreeMap<String, ColorClassifier>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ui_scale: None,
+            color_profile: VANILLA_COLOR_PROFILE.to_owned(),
+            red_detector: RedDetector::default(),
+            exclusion_zones: vec![
+                ExclusionZone {
+                    name: "Minimap".to_owned(),
+                    units: Units::UiPixels,
+                    anchor: Anchor::TopRight,
+                    shape: Shape::Rect {
+                        left_top: [-256., 0.],
+                        right_bottom: [0., 493.],
+                    },
+                    mask: false,
+                },
+                ExclusionZone {
+                    name: "Toolbar".to_owned(),
+                    units: Units::UiPixels,
+                    anchor: Anchor::Bottom,
+                    shape: Shape::Rect {
+                        left_top: [-257., -95.],
+                        right_bottom: [473., 0.],
+                    },
+                    mask: false,
+                },
+            ],
+            capture_backend: CaptureBackend::default(),
+            click_backend: ClickBackend::default(),
+            auto_fire: AutoFire::default(),
+            hotkey: None,
+            generator: Generator::default(),
+            refine: true,
+            safety: SafetyMargin::default(),
+            hits: HitRequirements::default(),
+            planner: PlannerConfig::default(),
+            color_profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Auto fire repeatedly captures, detects and fires until a scan finds nothing to shoot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoFire {
+    /// Minimum seconds from the start of one scan to the start of the next
+    pub interval: f64,
+    /// Seconds to wait after the last click before scanning again, so the shells have landed
+    /// and the targets they killed are not shot twice
+    pub cooldown: f64,
+}
+
+impl Default for AutoFire {
+    fn default() -> Self {
+        AutoFire {
+            interval: 2.,
+            cooldown: 8.,
+        }
+    }
+}
+
+impl AutoFire {
+    /// When to start the next scan, after a scan that started at scan_start has finished firing
+    pub fn next_scan(&self, scan_start: Instant) -> Instant {
+        let secs = |s: f64| Duration::from_secs_f64(s.max(0.));
+        (scan_start + secs(self.interval)).max(Instant::now() + secs(self.cooldown))
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&text)?;
+        if config.color_profile != VANILLA_COLOR_PROFILE
+            && !config.color_profiles.contains_key(&config.color_profile)
+        {
+            return Err(format!(
+                "color_profile {:?} is not one of the color_profiles",
+                config.color_profile
+            )
+            .into());
+        }
+        Ok(config)
+    }
+
+    /// Load the config at path, or the default config if there is no file at path
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Config, Box<dyn Error>> {
+        if !path.as_ref().exists() {
+            return Ok(Config::default());
+        }
+        Config::load(path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        // toml can't serialize enum variants that hold data, such as the backends, so go through
+        // JSON, which writes them as the single key tables they are read back from. Going
+        // through a Value also puts plain values before tables, which TOML requires.
+        let mut json = serde_json::to_value(self)?;
+        remove_nulls(&mut json);
+        let mut table: toml::value::Table = serde_json::from_value(json)?;
+        // toml only reads enum variants with data from inline tables
+        let mut text = String::new();
+        for key in INLINE_FIELDS {
+            if let Some(value @ toml::Value::Table(_)) = table.remove(key) {
+                text.push_str(&format!("{} = {}\n", key, inline_value(&value)));
+            }
+        }
+        text.push_str(&toml::to_string_pretty(&toml::Value::Table(table))?);
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// The classifier of the selected colour profile
+    pub fn classifier(&self) -> ColorClassifier {
+        self.color_profiles
+            .get(&self.color_profile)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replace the classifier of the selected colour profile
+    pub fn set_classifier(&mut self, classifier: ColorClassifier) {
+        self.color_profiles
+            .insert(self.color_profile.clone(), classifier);
+    }
+
+    /// The UI scale to use for a screenshot of the given height
+    pub fn ui_scale_for(&self, height: u32) -> f64 {
+        self.ui_scale
+            .unwrap_or(height as f64 / BASE_UI_HEIGHT as f64)
+    }
+}
+
+/// Fields of Config that are enums with variants that hold data
+const INLINE_FIELDS: [&str; 3] = ["capture_backend", "click_backend", "generator"];
+
+/// value written as a single line, with tables as inline tables
+fn inline_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) => {
+            let entries = table
+                .iter()
+                .map(|(k, v)| format!("{} = {}", k, inline_value(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        toml::Value::Array(values) => {
+            let values = values.iter().map(inline_value).collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// TOML has no null, so leave out the fields that are None instead
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// How the coordinates of an `ExclusionZone` are measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// Screen pixels
+    Pixels,
+    /// Screen pixels at a UI scale of 1.0, so the zone grows with the UI scale
+    UiPixels,
+    /// Fraction of the screen width/height, from 0.0 to 1.0
+    Relative,
+}
+
+/// The point of the screen that the coordinates of an `ExclusionZone` are measured from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    fn origin(&self, w: u32, h: u32) -> [f64; 2] {
+        let (w, h) = (w as f64, h as f64);
+        match self {
+            Anchor::TopLeft => [0., 0.],
+            Anchor::Top => [w / 2., 0.],
+            Anchor::TopRight => [w, 0.],
+            Anchor::Left => [0., h / 2.],
+            Anchor::Center => [w / 2., h / 2.],
+            Anchor::Right => [w, h / 2.],
+            Anchor::BottomLeft => [0., h],
+            Anchor::Bottom => [w / 2., h],
+            Anchor::BottomRight => [w, h],
+        }
+    }
+}
+
+/// Points are [w, h] pairs in the units of the zone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Rect {
+        left_top: [f64; 2],
+        right_bottom: [f64; 2],
+    },
+    Polygon {
+        points: Vec<[f64; 2]>,
+    },
+}
+
+/// A named area of the screen that must never be clicked, such as part of the game UI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExclusionZone {
+    pub name: String,
+    pub units: Units,
+    pub anchor: Anchor,
+    pub shape: Shape,
+    /// Also black out the zone in the screenshot before detecting, for areas that are not the
+    /// map at all and could be mistaken for enemies
+    #[serde(default)]
+    pub mask: bool,
+}
+
+impl ExclusionZone {
+    /// A rectangle covering the given fractions of the screen
+    pub fn relative_rect(
+        name: impl Into<String>,
+        left_top: [f64; 2],
+        right_bottom: [f64; 2],
+    ) -> Self {
+        ExclusionZone {
+            name: name.into(),
+            units: Units::Relative,
+            anchor: Anchor::TopLeft,
+            shape: Shape::Rect {
+                left_top,
+                right_bottom,
+            },
+            mask: false,
+        }
+    }
+
+    /// A masked rectangle of screenshot pixels, for a window on top of the game
+    pub fn window(name: impl Into<String>, left_top: Coord, right_bottom: Coord) -> Self {
+        ExclusionZone {
+            name: name.into(),
+            units: Units::Pixels,
+            anchor: Anchor::TopLeft,
+            shape: Shape::Rect {
+                left_top: [left_top.w as f64, left_top.h as f64],
+                right_bottom: [right_bottom.w as f64, right_bottom.h as f64],
+            },
+            mask: true,
+        }
+    }
+
+    /// Convert the points of the zone to screen pixels
+    /// w: width of the screenshot in pixels
+    /// h: height of the screenshot in pixels
+    pub fn to_pixels(&self, w: u32, h: u32, ui_scale: f64) -> Vec<[f64; 2]> {
+        let origin = self.anchor.origin(w, h);
+        let scale = match self.units {
+            Units::Pixels => [1., 1.],
+            Units::UiPixels => [ui_scale, ui_scale],
+            Units::Relative => [w as f64, h as f64],
+        };
+        let points = match &self.shape {
+            Shape::Rect {
+                left_top,
+                right_bottom,
+            } => vec![
+                *left_top,
+                [right_bottom[0], left_top[1]],
+                *right_bottom,
+                [left_top[0], right_bottom[1]],
+            ],
+            Shape::Polygon { points } => points.clone(),
+        };
+        points
+            .iter()
+            .map(|p| [origin[0] + p[0] * scale[0], origin[1] + p[1] * scale[1]])
+            .collect()
+    }
+
+    /// Check if a click on a screen of the given size lands inside this zone
+    pub fn contains(&self, click: Coord, w: u32, h: u32, ui_scale: f64) -> bool {
+        let points = self.to_pixels(w, h, ui_scale);
+        if let Shape::Rect { .. } = self.shape {
+            let (left, right) = (
+                points[0][0].min(points[2][0]),
+                points[0][0].max(points[2][0]),
+            );
+            let (top, bottom) = (
+                points[0][1].min(points[2][1]),
+                points[0][1].max(points[2][1]),
+            );
+            let (x, y) = (click.w as f64, click.h as f64);
+            return x >= left && x <= right && y >= top && y <= bottom;
+        }
+        point_in_polygon(&points, [click.w as f64, click.h as f64])
+    }
+}
+
+/// Even-odd ray casting test
+fn point_in_polygon(points: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    let mut j = points.len().wrapping_sub(1);
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[j]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
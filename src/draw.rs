@@ -0,0 +1,133 @@
+//! Module to draw bounding boxes and clicks over a screenshot, to debug the detectors
+
+use image::Rgb;
+use image::RgbImage;
+use imageproc::drawing::{draw_hollow_circle_mut, draw_hollow_rect_mut};
+use imageproc::rect::Rect;
+
+use crate::color::ColorClassifier;
+use crate::components::process_red_components_stages;
+use crate::config::Config;
+use crate::legit::process_red_stages;
+use crate::screen::{BoundingBox, Coord};
+use crate::{
+    mask_excluded_areas, plan_targets, red_remote_radius, remove_clicks_in_excluded_areas,
+    Detections, Detector, PlanOptions, RedDetector, TargetClass,
+};
+
+/// Colour of each stage of the red detector, chosen to stand out against red enemies
+const STAGE_COLORS: [Rgb<u8>; 6] = [
+    Rgb([0, 0x80, 0xff]),
+    Rgb([0, 0xff, 0]),
+    Rgb([0xff, 0xff, 0]),
+    Rgb([0, 0xff, 0xff]),
+    Rgb([0xff, 0x80, 0]),
+    Rgb([0xff, 0xff, 0xff]),
+];
+
+const CLICK_COLOR: Rgb<u8> = Rgb([0xff, 0, 0xff]);
+
+/// Colour of the pixels that look like enemies in classifier_preview
+pub const ENEMY_PREVIEW_COLOR: Rgb<u8> = Rgb([0, 0xff, 0]);
+
+/// Outline each bb, covering the same pixels as BoundingBox::enumerate
+pub fn draw_bbs(img: &mut RgbImage, bbs: &[BoundingBox], color: Rgb<u8>) {
+    for bb in bbs {
+        let rect = Rect::at(bb.left_top.w as i32, bb.left_top.h as i32)
+            .of_size(bb.w() as u32 + 1, bb.h() as u32 + 1);
+        draw_hollow_rect_mut(img, rect, color);
+    }
+}
+
+/// Draw the blast radius of each click
+pub fn draw_clicks(img: &mut RgbImage, clicks: &[Coord], remote_radius: u32, color: Rgb<u8>) {
+    for click in clicks {
+        draw_hollow_circle_mut(
+            img,
+            (click.w as i32, click.h as i32),
+            remote_radius as i32,
+            color,
+        );
+    }
+}
+
+/// Run the red detector of config on img and draw one overlay per stage.
+/// Each overlay shows the bbs of its stage and all earlier stages, except the final overlay
+/// which only shows the bbs that survived the final filter.
+/// The clicks generated from the final bbs are drawn on top of every overlay, planned and
+/// filtered with the settings of config the same way `process_image` does.
+pub fn red_stage_overlays(img: &RgbImage, config: &Config) -> Vec<(&'static str, RgbImage)> {
+    let ui_scale = config.ui_scale_for(img.height());
+    let mut masked = img.clone();
+    mask_excluded_areas(&mut masked, &config.exclusion_zones, ui_scale);
+    let (stages, classes, spawner_width) = match config.red_detector {
+        RedDetector::Scan => {
+            let (stages, spawner_width) = process_red_stages(&mut masked, &config.classifier());
+            let found = stages.last().map_or(0, |s| s.bbs.len());
+            (stages, vec![TargetClass::Unknown; found], spawner_width)
+        }
+        RedDetector::Components => process_red_components_stages(&masked, &config.classifier()),
+    };
+    let detections = Detections {
+        detector: Detector::Red,
+        classes,
+        bbs: stages.last().map(|s| s.bbs.clone()).unwrap_or_default(),
+        remote_radius: red_remote_radius(spawner_width),
+        spawner_width: Some(spawner_width),
+    };
+    let mut clicks = plan_targets(
+        &detections.targets(&config.hits),
+        PlanOptions {
+            generator: config.generator,
+            refine: config.refine,
+            safety: config.safety,
+            planner: config.planner,
+            ..PlanOptions::for_detections(&detections, img)
+        },
+    );
+    remove_clicks_in_excluded_areas(
+        &mut clicks,
+        &config.exclusion_zones,
+        img.width(),
+        img.height(),
+        ui_scale,
+    );
+    let remote_radius = detections.remote_radius;
+
+    let mut overlays = vec![];
+    for (i, stage) in stages.iter().enumerate() {
+        let mut overlay = img.clone();
+        if i + 1 == stages.len() {
+            draw_bbs(
+                &mut overlay,
+                &stage.bbs,
+                STAGE_COLORS[i % STAGE_COLORS.len()],
+            );
+        } else {
+            for (j, earlier) in stages[..=i].iter().enumerate() {
+                draw_bbs(
+                    &mut overlay,
+                    &earlier.bbs,
+                    STAGE_COLORS[j % STAGE_COLORS.len()],
+                );
+            }
+        }
+        draw_clicks(&mut overlay, &clicks, remote_radius, CLICK_COLOR);
+        overlays.push((stage.name, overlay));
+    }
+    overlays
+}
+
+/// Show which pixels of img the classifier sees as enemies: enemy pixels are drawn in
+/// ENEMY_PREVIEW_COLOR and everything else is darkened
+pub fn classifier_preview(img: &RgbImage, classifier: &ColorClassifier) -> RgbImage {
+    let mut preview = img.clone();
+    for px in preview.pixels_mut() {
+        *px = if classifier.looks_like_enemy(px) {
+            ENEMY_PREVIEW_COLOR
+        } else {
+            Rgb(px.0.map(|c| c / 3))
+        };
+    }
+    preview
+}
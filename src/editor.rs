@@ -0,0 +1,142 @@
+//! GUI mode to draw exclusion zones on top of a screenshot
+
+use egui::{Color32, Pos2, Rect, Sense, Stroke};
+use image::RgbImage;
+
+use iron_dome::config::{Config, ExclusionZone};
+
+/// Window size while the editor is open
+pub const EDITOR_WINDOW_SIZE: egui::Vec2 = egui::vec2(1280., 860.);
+
+#[derive(Default)]
+pub struct ZoneEditor {
+    texture: Option<egui::TextureHandle>,
+    /// Screenshot size in pixels, that the zones are drawn on
+    img_size: [u32; 2],
+    /// Where the current drag started, as a fraction of the screenshot size
+    drag_start: Option<Pos2>,
+    drag_end: Option<Pos2>,
+    status: String,
+}
+
+impl ZoneEditor {
+    /// Use img as the background that zones are drawn on
+    pub fn set_image(&mut self, ctx: &egui::Context, img: &RgbImage) {
+        let pixels = img
+            .pixels()
+            .map(|px| Color32::from_rgb(px[0], px[1], px[2]))
+            .collect();
+        let color_image = egui::ColorImage {
+            size: [img.width() as usize, img.height() as usize],
+            pixels,
+        };
+        self.texture =
+            Some(ctx.load_texture("screenshot", color_image, egui::TextureFilter::Linear));
+        self.img_size = [img.width(), img.height()];
+    }
+
+    pub fn has_image(&self) -> bool {
+        self.texture.is_some()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, config: &mut Config, config_path: &str) {
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                self.status = match config.save(config_path) {
+                    Ok(()) => format!("Saved to {}", config_path),
+                    Err(e) => format!("Failed to save {}: {}", config_path, e),
+                };
+            }
+            if ui.button("Reset to defaults").clicked() {
+                config.exclusion_zones = Config::default().exclusion_zones;
+            }
+            ui.label(&self.status);
+        });
+        ui.label("Drag on the screenshot to add a zone");
+
+        let mut remove = None;
+        for (i, zone) in config.exclusion_zones.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut zone.name);
+                ui.label(format!("{:?} from {:?}", zone.units, zone.anchor));
+                if ui.button("Delete").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            config.exclusion_zones.remove(i);
+        }
+
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return,
+        };
+        let [img_w, img_h] = self.img_size;
+        let avail = ui.available_size();
+        let fit = f32::min(avail.x / img_w as f32, avail.y / img_h as f32);
+        let size = egui::vec2(img_w as f32 * fit, img_h as f32 * fit);
+        let response = ui.add(egui::Image::new(texture.id(), size).sense(Sense::drag()));
+        let rect = response.rect;
+        let to_relative = |pos: Pos2| {
+            let rel = (pos - rect.min) / rect.size();
+            Pos2::new(rel.x.clamp(0., 1.), rel.y.clamp(0., 1.))
+        };
+        let from_relative = |rel: Pos2| rect.min + rel.to_vec2() * rect.size();
+
+        let painter = ui.painter_at(rect);
+        let ui_scale = config.ui_scale_for(img_h);
+        for zone in &config.exclusion_zones {
+            let points = zone
+                .to_pixels(img_w, img_h, ui_scale)
+                .iter()
+                .map(|p| {
+                    from_relative(Pos2::new(
+                        p[0] as f32 / img_w as f32,
+                        p[1] as f32 / img_h as f32,
+                    ))
+                })
+                .collect::<Vec<_>>();
+            if let Some(first) = points.first() {
+                painter.text(
+                    *first,
+                    egui::Align2::LEFT_TOP,
+                    &zone.name,
+                    egui::FontId::default(),
+                    Color32::WHITE,
+                );
+            }
+            painter.add(egui::Shape::closed_line(
+                points,
+                Stroke::new(2., Color32::from_rgb(0xff, 0x80, 0)),
+            ));
+        }
+
+        if response.drag_started() {
+            self.drag_start = response.interact_pointer_pos().map(to_relative);
+        }
+        if let Some(pos) = response.interact_pointer_pos() {
+            self.drag_end = Some(to_relative(pos));
+        }
+        if let (Some(start), Some(end)) = (self.drag_start, self.drag_end) {
+            painter.rect_stroke(
+                Rect::from_two_pos(from_relative(start), from_relative(end)),
+                0.,
+                Stroke::new(2., Color32::YELLOW),
+            );
+            if response.drag_released() {
+                self.drag_start = None;
+                self.drag_end = None;
+                let drawn = Rect::from_two_pos(start, end);
+                if drawn.width() > 0. && drawn.height() > 0. {
+                    let name = format!("Zone {}", config.exclusion_zones.len() + 1);
+                    config.exclusion_zones.push(ExclusionZone::relative_rect(
+                        name,
+                        [drawn.min.x as f64, drawn.min.y as f64],
+                        [drawn.max.x as f64, drawn.max.y as f64],
+                    ));
+                }
+            }
+        }
+    }
+}
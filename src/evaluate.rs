@@ -0,0 +1,196 @@
+//! Module to score the detectors and click generator against hand annotated screenshots
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::screen::BoundingBox;
+use crate::{detect, plan, DetectOptions, Detector, PlanOptions, RedDetector, TargetClass};
+
+/// Ground truth for a single screenshot, stored next to the image with a `.toml` extension
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// No plan hits every target with fewer artillery shells than this
+    pub min_shells: usize,
+    /// The class of each target, when it is known
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub classes: Vec<TargetClass>,
+    /// The part of the screenshot that is annotated, when it has too many targets to annotate
+    /// them all. Detections outside it are not scored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<BoundingBox>,
+    /// Every spawner/worm visible in the screenshot
+    pub targets: Vec<BoundingBox>,
+}
+
+impl Annotation {
+    /// The annotation file belonging to an image, eg `zoom/z1.png` -> `zoom/z1.toml`
+    pub fn path_for(image: impl AsRef<Path>) -> PathBuf {
+        image.as_ref().with_extension("toml")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Annotation, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Score of one detector on one annotated screenshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub detector: Detector,
+    /// Algorithm of the red detector, when detector is Red
+    pub red_detector: Option<RedDetector>,
+    pub targets: usize,
+    pub detected: usize,
+    /// Detected bbs that overlap an annotated target
+    pub true_positives: usize,
+    /// Detected bbs that overlap no annotated target
+    pub false_positives: usize,
+    /// Annotated targets that no detected bb overlaps
+    pub missed: usize,
+    /// Annotated targets inside the blast radius of a generated click
+    pub hit: usize,
+    pub shells: usize,
+    pub min_shells: usize,
+}
+
+impl Evaluation {
+    pub fn precision(&self) -> f64 {
+        if self.detected == 0 {
+            return 1.0;
+        }
+        self.true_positives as f64 / self.detected as f64
+    }
+
+    pub fn recall(&self) -> f64 {
+        if self.targets == 0 {
+            return 1.0;
+        }
+        (self.targets - self.missed) as f64 / self.targets as f64
+    }
+
+    /// Add the counts of other to self, to total up a detector over many images
+    pub fn add(&mut self, other: &Evaluation) {
+        self.targets += other.targets;
+        self.detected += other.detected;
+        self.true_positives += other.true_positives;
+        self.false_positives += other.false_positives;
+        self.missed += other.missed;
+        self.hit += other.hit;
+        self.shells += other.shells;
+        self.min_shells += other.min_shells;
+    }
+
+    /// Name of the detector, with the algorithm of the red detector, eg red:components
+    pub fn label(&self) -> String {
+        match self.red_detector {
+            Some(red_detector) => format!("{:?}:{}", self.detector, red_detector),
+            None => format!("{:?}", self.detector),
+        }
+    }
+
+    pub fn header() -> &'static str {
+        "detector        targets  detected     tp     fp  missed  precision  recall    hit  shells  optimal"
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<14}  {:>7}  {:>8}  {:>5}  {:>5}  {:>6}  {:>9.3}  {:>6.3}  {:>5}  {:>6}  {:>7}",
+            self.label(),
+            self.targets,
+            self.detected,
+            self.true_positives,
+            self.false_positives,
+            self.missed,
+            self.precision(),
+            self.recall(),
+            self.hit,
+            self.shells,
+            self.min_shells,
+        )
+    }
+}
+
+/// Run a single detector on img and compare the detections and generated clicks against annotation
+/// config: the colour classifier, red detector and click generator settings, the same ones a shot
+/// uses
+pub fn evaluate(
+    img: &RgbImage,
+    annotation: &Annotation,
+    detector: Detector,
+    config: &Config,
+) -> Evaluation {
+    let options = DetectOptions {
+        scan_debug: detector == Detector::Debug,
+        scan_red: detector == Detector::Red,
+        classifier: config.classifier(),
+        red_detector: config.red_detector,
+    };
+    let red_detector = options.scan_red.then_some(options.red_detector);
+    let mut detections = detect(img, options);
+    if let Some(region) = &annotation.region {
+        let (bbs, classes) = detections
+            .bbs
+            .iter()
+            .zip(&detections.classes)
+            .filter(|(bb, _)| bb.collides_with_bb(region))
+            .unzip();
+        detections.bbs = bbs;
+        detections.classes = classes;
+    }
+    let clicks = plan(
+        &detections.bbs,
+        PlanOptions {
+            generator: config.generator,
+            refine: config.refine,
+            safety: config.safety,
+            planner: config.planner,
+            ..PlanOptions::for_detections(&detections, img)
+        },
+    );
+
+    let true_positives = detections
+        .bbs
+        .iter()
+        .filter(|bb| annotation.targets.iter().any(|t| t.collides_with_bb(bb)))
+        .count();
+    let missed = annotation
+        .targets
+        .iter()
+        .filter(|t| !detections.bbs.iter().any(|bb| t.collides_with_bb(bb)))
+        .count();
+    let hit = annotation
+        .targets
+        .iter()
+        .filter(|t| {
+            clicks
+                .iter()
+                .any(|click| t.collides_with_circle(*click, detections.remote_radius))
+        })
+        .count();
+
+    Evaluation {
+        detector,
+        red_detector,
+        targets: annotation.targets.len(),
+        detected: detections.bbs.len(),
+        true_positives,
+        false_positives: detections.bbs.len() - true_positives,
+        missed,
+        hit,
+        shells: clicks.len(),
+        min_shells: annotation.min_shells,
+    }
+}
@@ -1,104 +1,171 @@
 //! Module to generate clicks from a list of bounding boxes
 
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tiny_rng::{Rand, Rng};
 
 use crate::screen::BoundingBox;
+use crate::screen::BoxGrid;
 use crate::screen::Coord;
+use crate::Target;
 
-const NUM_RANDOM_GUESSES: usize = 10;
-const NUM_RANDOM_SAMPLES: usize = 1000;
+/// Settings of the random generator, to trade planning time against shells used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlannerConfig {
+    /// Seed of the first guess, each following guess uses the next seed
+    pub seed: u64,
+    /// Threads to run the guesses on, 0 for one per core
+    pub threads: usize,
+    /// Independent greedy guesses, of which the one with the fewest clicks is kept
+    pub guesses: usize,
+    /// Random clicks tried around each target
+    pub samples: usize,
+    /// Once this many milliseconds have passed, stop the guesses that are still running as soon
+    /// as one guess has finished
+    pub deadline_ms: Option<u64>,
+}
 
-/// Generate clicks from a set of bounding boxes
-/// bbs: a slice of BoundingBox objects to target
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        PlannerConfig {
+            seed: 0,
+            threads: 0,
+            guesses: 10,
+            samples: 1000,
+            deadline_ms: None,
+        }
+    }
+}
+
+/// Generate clicks from a set of targets, the highest priority targets first.
+/// Each click is placed to hit the most targets, and of those the highest priority ones.
+/// The result only depends on config's seed, unless its deadline is hit.
+/// Returns the clicks of the best guess, and how many clicks each finished guess found, fewest
+/// first.
+/// targets: a slice of Target objects to hit once each
 /// remote_radius: estimated size of the artillery remote target area (in pixels)
 /// w: width of the image in pixels (to keep generated clicks in bounds)
 /// h: height of the image in pixels (to keep generated clicks in bounds)
-pub fn gen_clicks_from_bbs_rand(
-    bbs: &[BoundingBox],
+pub fn gen_clicks_from_targets_rand(
+    targets: &[Target],
     remote_radius: u32,
     w: u32,
     h: u32,
-) -> Vec<Coord> {
-    let clicks: Arc<Mutex<Vec<Vec<Coord>>>> = Arc::new(Mutex::new(vec![]));
+    config: PlannerConfig,
+) -> (Vec<Coord>, Vec<usize>) {
+    let deadline = config
+        .deadline_ms
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+    let finished_one = AtomicBool::new(false);
+    let targets = by_priority(targets);
+    let grid = BoxGrid::new(&bbs_of(&targets), 2 * remote_radius as i64 + 1);
 
-    println!(
-        "{} * {} = {}",
-        std::mem::size_of::<BoundingBox>(),
-        bbs.len(),
-        std::mem::size_of::<BoundingBox>() * bbs.len()
-    );
-
-    let it = std::time::Instant::now();
-
-    std::thread::scope(|scope| {
-        let mut returns = vec![];
-        for id in 0..NUM_RANDOM_GUESSES {
-            let mut bbs: Vec<BoundingBox> = bbs.to_vec();
-            let clicks = clicks.clone();
-
-            returns.push(scope.spawn(move || {
-                let mut rng = tiny_rng::Rng::from_seed(id as u64);
-                let mut current_clicks = vec![];
-                while !bbs.is_empty() {
-                    let bb = bbs.pop().unwrap();
-                    // Default to click the corner
-                    let mut best_click = bb.left_top;
-                    let mut best_hits = 0;
-                    for _ in 0..NUM_RANDOM_SAMPLES {
-                        // Generate a new random click, that likely hits this bb
-                        let test_click = get_rand_click(&mut rng, &bb, remote_radius, w, h);
-                        if bb.collides_with_circle(test_click, remote_radius) {
-                            // We must hit self, regardless of how many other bbs we might hit
-                            let bbs = &bbs;
-                            let hits = count_collisions_single(&bbs, remote_radius, test_click) + 1;
-                            if hits > best_hits {
-                                best_click = test_click;
-                                best_hits = hits;
-                            }
-                        }
+    let guess = |id: usize| {
+        let mut remaining = Remaining::new(&targets, &grid);
+        let mut rng = Rng::from_seed(config.seed.wrapping_add(id as u64));
+        let mut current_clicks = vec![];
+        while let Some(target) = remaining.pop() {
+            if deadline.is_some_and(|deadline| Instant::now() > deadline)
+                && finished_one.load(Ordering::Relaxed)
+            {
+                return None;
+            }
+            let bb = target.bb;
+            // Default to click the corner
+            let mut best_click = bb.left_top;
+            let mut best_hits = (0, 0);
+            for _ in 0..config.samples {
+                // Generate a new random click, that likely hits this bb
+                let test_click = get_rand_click(&mut rng, &bb, remote_radius, w, h);
+                if bb.collides_with_circle(test_click, remote_radius) {
+                    // We must hit self, regardless of how many other bbs we might hit
+                    let (hits, weight) = remaining.score_click(test_click, remote_radius);
+                    let hits = (hits + 1, weight + target.class.priority() + 1);
+                    if hits > best_hits {
+                        best_click = test_click;
+                        best_hits = hits;
                     }
-                    current_clicks.push(best_click);
-                    /*if current_clicks.len() > best_clicks.len() && !best_clicks.is_empty() {
-                        break;
-                    }*/
-                    // Remove anything hit by the most recent click
-                    bbs.retain(|bb| {
-                        if let Some(click) = current_clicks.last() {
-                            if bb.collides_with_circle(*click, remote_radius) {
-                                return false;
-                            }
-                        }
-                        true
-                    });
                 }
-                clicks.lock().unwrap().push(current_clicks);
-            }));
+            }
+            current_clicks.push(best_click);
+            remaining.remove_hit(best_click, remote_radius);
         }
-    });
+        finished_one.store(true, Ordering::Relaxed);
+        Some(current_clicks)
+    };
+    // Collected in guess order, so ties are broken the same way whichever guess finishes first
+    let run = || {
+        (0..config.guesses.max(1))
+            .into_par_iter()
+            .map(guess)
+            .collect::<Vec<_>>()
+    };
+    // The clicks don't depend on the thread count, so a pool that can't be started falls back to
+    // the global one
+    let guesses = match (config.threads > 0)
+        .then(|| thread_pool(config.threads))
+        .and_then(Result::ok)
+    {
+        Some(pool) => pool.install(run),
+        None => run(),
+    };
 
-    // Take the found clicks out
-    let v = Arc::try_unwrap(clicks).unwrap().into_inner().unwrap();
-    let mut counts = v.iter().map(|bucket| bucket.len()).collect::<Vec<_>>();
+    let guesses = guesses.into_iter().flatten().collect::<Vec<_>>();
+    let mut counts = guesses
+        .iter()
+        .map(|bucket| bucket.len())
+        .collect::<Vec<_>>();
     counts.sort();
 
-    println!("Threads found {:#?} clicks in {:?}", counts, it.elapsed());
-    v.into_iter().min_by_key(|bucket| bucket.len()).unwrap()
+    let best = guesses
+        .into_iter()
+        .min_by_key(|bucket| bucket.len())
+        .unwrap();
+    (best, counts)
 }
 
-pub fn gen_clicks_from_bbs_fixed(
-    bbs: &[BoundingBox],
+/// Pool of the last number of planner threads asked for, kept so every plan doesn't start new
+/// threads
+static THREAD_POOL: Mutex<Option<(usize, Arc<rayon::ThreadPool>)>> = Mutex::new(None);
+
+/// A pool of threads threads, the same one as last time unless threads has changed
+fn thread_pool(threads: usize) -> Result<Arc<rayon::ThreadPool>, rayon::ThreadPoolBuildError> {
+    let mut cached = THREAD_POOL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((count, pool)) = &*cached {
+        if *count == threads {
+            return Ok(pool.clone());
+        }
+    }
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?,
+    );
+    *cached = Some((threads, pool.clone()));
+    Ok(pool)
+}
+
+/// The same as gen_clicks_from_targets_rand, but tries every click position around each target
+pub fn gen_clicks_from_targets_fixed(
+    targets: &[Target],
     remote_radius: u32,
     screen_w: u32,
     screen_h: u32,
 ) -> Vec<Coord> {
-    let mut bbs = bbs.to_vec();
+    let targets = by_priority(targets);
+    let grid = BoxGrid::new(&bbs_of(&targets), 2 * remote_radius as i64 + 1);
+    let mut remaining = Remaining::new(&targets, &grid);
     let mut current_clicks = vec![];
-    while !bbs.is_empty() {
-        let bb = bbs.pop().unwrap();
+    while let Some(target) = remaining.pop() {
+        let bb = target.bb;
         // Default to click the corner
         let mut best_click = bb.left_top;
-        let mut best_hits = 0;
+        let mut best_hits = (0, 0);
 
         for w in (i64::max(bb.left_top.w - remote_radius as i64, 0)
             ..=i64::min(bb.right_bottom.w + remote_radius as i64, screen_w as i64))
@@ -115,8 +182,8 @@ pub fn gen_clicks_from_bbs_fixed(
                 }*/
                 if bb.collides_with_circle(test_click, remote_radius) {
                     // We must hit self, regardless of how many other bbs we might hit
-                    let bbs = &bbs;
-                    let hits = count_collisions_single(&bbs, remote_radius, test_click) + 1;
+                    let (hits, weight) = remaining.score_click(test_click, remote_radius);
+                    let hits = (hits + 1, weight + target.class.priority() + 1);
                     if hits > best_hits {
                         best_click = test_click;
                         best_hits = hits;
@@ -125,17 +192,85 @@ pub fn gen_clicks_from_bbs_fixed(
             }
         }
         current_clicks.push(best_click);
-        // Remove anything hit by the most recent click
-        bbs.retain(|bb| {
-            if let Some(click) = current_clicks.last() {
-                if bb.collides_with_circle(*click, remote_radius) {
-                    return false;
-                }
+        remaining.remove_hit(best_click, remote_radius);
+    }
+    current_clicks
+}
+
+/// Copy of targets in the order the greedy generators pop them, so the highest priority
+/// targets come off the end first
+fn by_priority(targets: &[Target]) -> Vec<Target> {
+    let mut targets = targets.to_vec();
+    targets.sort_by_key(|target| target.class.priority());
+    targets
+}
+
+fn bbs_of(targets: &[Target]) -> Vec<BoundingBox> {
+    targets.iter().map(|target| target.bb).collect()
+}
+
+/// The targets a greedy generator has not hit yet, popped from the end of targets
+struct Remaining<'a> {
+    targets: &'a [Target],
+    /// Index of the targets' bbs
+    grid: &'a BoxGrid,
+    alive: Vec<bool>,
+    /// Every target from here on has been popped or hit
+    end: usize,
+}
+
+impl<'a> Remaining<'a> {
+    fn new(targets: &'a [Target], grid: &'a BoxGrid) -> Self {
+        Remaining {
+            targets,
+            grid,
+            alive: vec![true; targets.len()],
+            end: targets.len(),
+        }
+    }
+
+    /// Take the last target that has not been hit
+    fn pop(&mut self) -> Option<Target> {
+        while self.end > 0 {
+            self.end -= 1;
+            if self.alive[self.end] {
+                self.alive[self.end] = false;
+                return Some(self.targets[self.end]);
+            }
+        }
+        None
+    }
+
+    /// How many remaining targets click hits, and the sum of their priorities (counting from 1)
+    fn score_click(&self, click: Coord, remote_radius: u32) -> (usize, u32) {
+        let mut score = (0, 0);
+        self.grid.query_circle(click, remote_radius, |i| {
+            if self.alive[i] {
+                score.0 += 1;
+                score.1 += self.targets[i].class.priority() + 1;
             }
-            true
         });
+        score
+    }
+
+    /// Remove every target that click hits
+    fn remove_hit(&mut self, click: Coord, remote_radius: u32) {
+        let alive = &mut self.alive;
+        self.grid
+            .query_circle(click, remote_radius, |i| alive[i] = false);
     }
-    current_clicks
+}
+
+/// Sort clicks so those that hit the highest priority targets are fired first, then top to bottom
+pub fn order_clicks(clicks: &mut [Coord], targets: &[Target], remote_radius: u32) {
+    clicks.sort_by_cached_key(|click| {
+        let priority = targets
+            .iter()
+            .filter(|target| target.bb.collides_with_circle(*click, remote_radius))
+            .map(|target| target.class.priority())
+            .max();
+        (Reverse(priority), click.h)
+    });
 }
 
 fn get_rand_click(rng: &mut Rng, bb: &BoundingBox, remote_radius: u32, w: u32, h: u32) -> Coord {
@@ -164,3 +299,216 @@ fn count_collisions_single(bbs: &[BoundingBox], remote_radius: u32, click: Coord
     }
     ct
 }
+
+/// Most passes of refine_clicks, stopping early once a pass saves nothing
+const REFINE_PASSES: usize = 10;
+/// How far in pixels refine_clicks moves a click looking for more targets to hit
+const JIGGLE_DISTANCE: i64 = 8;
+
+/// Improve a set of clicks that hits every bb: remove clicks whose bbs are all hit by other
+/// clicks, replace pairs of clicks with a single click that hits the bbs only they hit, and move
+/// clicks to hit more bbs, so more clicks become redundant.
+/// Every bb hit by the original clicks is still hit by the refined clicks.
+pub fn refine_clicks(
+    clicks: &[Coord],
+    bbs: &[BoundingBox],
+    remote_radius: u32,
+    w: u32,
+    h: u32,
+) -> Vec<Coord> {
+    let mut refined = clicks.to_vec();
+    for _ in 0..REFINE_PASSES {
+        let count = refined.len();
+        remove_redundant_clicks(&mut refined, bbs, remote_radius);
+        merge_click_pairs(&mut refined, bbs, remote_radius, w, h);
+        jiggle_clicks(&mut refined, bbs, remote_radius, w, h);
+        remove_redundant_clicks(&mut refined, bbs, remote_radius);
+        if refined.len() == count {
+            break;
+        }
+    }
+    refined
+}
+
+/// For each bb, how many of the clicks hit it
+fn count_hits(clicks: &[Coord], bbs: &[BoundingBox], remote_radius: u32) -> Vec<usize> {
+    bbs.iter()
+        .map(|bb| {
+            clicks
+                .iter()
+                .filter(|click| bb.collides_with_circle(**click, remote_radius))
+                .count()
+        })
+        .collect()
+}
+
+/// Indexes of the bbs that click hits
+fn hit_by(click: Coord, bbs: &[BoundingBox], remote_radius: u32) -> Vec<usize> {
+    (0..bbs.len())
+        .filter(|&i| bbs[i].collides_with_circle(click, remote_radius))
+        .collect()
+}
+
+/// Remove clicks that only hit bbs that other clicks also hit, clicks that hit the fewest first
+fn remove_redundant_clicks(clicks: &mut Vec<Coord>, bbs: &[BoundingBox], remote_radius: u32) {
+    let mut hits = count_hits(clicks, bbs, remote_radius);
+    let mut hit_lists = clicks
+        .iter()
+        .enumerate()
+        .map(|(i, click)| (i, hit_by(*click, bbs, remote_radius)))
+        .collect::<Vec<_>>();
+    hit_lists.sort_by_key(|(_, hit)| hit.len());
+    let mut keep = vec![true; clicks.len()];
+    for (i, hit) in hit_lists {
+        if hit.iter().all(|&bb| hits[bb] > 1) {
+            for bb in hit {
+                hits[bb] -= 1;
+            }
+            keep[i] = false;
+        }
+    }
+    let mut keep = keep.into_iter();
+    clicks.retain(|_| keep.next().unwrap());
+}
+
+/// Replace two clicks with one, when a single click can hit every bb that only they hit
+fn merge_click_pairs(
+    clicks: &mut Vec<Coord>,
+    bbs: &[BoundingBox],
+    remote_radius: u32,
+    w: u32,
+    h: u32,
+) {
+    let r = remote_radius as i64;
+    let mut hits = count_hits(clicks, bbs, remote_radius);
+    let mut hit_lists = clicks
+        .iter()
+        .map(|click| hit_by(*click, bbs, remote_radius))
+        .collect::<Vec<_>>();
+    let mut removed = vec![false; clicks.len()];
+    for i in 0..clicks.len() {
+        for j in i + 1..clicks.len() {
+            if removed[i] || removed[j] {
+                continue;
+            }
+            // A click can only hit bbs within its radius, so the clicks it replaces are close
+            let (a, b) = (clicks[i], clicks[j]);
+            if (a.w - b.w).abs() > 4 * (r + 1) || (a.h - b.h).abs() > 4 * (r + 1) {
+                continue;
+            }
+            let mut only_hit_by_pair = hit_lists[i].clone();
+            only_hit_by_pair.extend(&hit_lists[j]);
+            only_hit_by_pair.sort_unstable();
+            only_hit_by_pair.dedup();
+            only_hit_by_pair.retain(|&bb| {
+                let by_pair =
+                    hit_lists[i].contains(&bb) as usize + hit_lists[j].contains(&bb) as usize;
+                hits[bb] == by_pair
+            });
+            if only_hit_by_pair.is_empty() {
+                // Both are redundant, which remove_redundant_clicks deals with
+                continue;
+            }
+            if let Some(merged) =
+                find_click_hitting_all(&only_hit_by_pair, bbs, remote_radius, w, h)
+            {
+                for &bb in hit_lists[i].iter().chain(&hit_lists[j]) {
+                    hits[bb] -= 1;
+                }
+                let merged_hits = hit_by(merged, bbs, remote_radius);
+                for &bb in &merged_hits {
+                    hits[bb] += 1;
+                }
+                clicks[i] = merged;
+                hit_lists[i] = merged_hits;
+                hit_lists[j].clear();
+                removed[j] = true;
+            }
+        }
+    }
+    let mut removed = removed.into_iter();
+    clicks.retain(|_| !removed.next().unwrap());
+}
+
+/// Search the area where the bbs grown by the radius overlap for a click that hits all of them
+fn find_click_hitting_all(
+    targets: &[usize],
+    bbs: &[BoundingBox],
+    remote_radius: u32,
+    w: u32,
+    h: u32,
+) -> Option<Coord> {
+    let r = remote_radius as i64;
+    let (mut left, mut top, mut right, mut bottom) = (0, 0, w as i64, h as i64);
+    for &i in targets {
+        left = left.max(bbs[i].left_top.w - r);
+        top = top.max(bbs[i].left_top.h - r);
+        right = right.min(bbs[i].right_bottom.w + r);
+        bottom = bottom.min(bbs[i].right_bottom.h + r);
+    }
+    for h in top..=bottom {
+        for w in left..=right {
+            let click = Coord { w, h };
+            if targets
+                .iter()
+                .all(|&i| bbs[i].collides_with_circle(click, remote_radius))
+            {
+                return Some(click);
+            }
+        }
+    }
+    None
+}
+
+/// Move each click to the nearby position that hits the most bbs, while still hitting the bbs
+/// that no other click hits
+fn jiggle_clicks(clicks: &mut [Coord], bbs: &[BoundingBox], remote_radius: u32, w: u32, h: u32) {
+    let mut hits = count_hits(clicks, bbs, remote_radius);
+    for click in clicks.iter_mut() {
+        let nearby = (0..bbs.len())
+            .filter(|&i| {
+                bbs[i].collides_fast_calc(*click, remote_radius as i64 + JIGGLE_DISTANCE + 1)
+            })
+            .collect::<Vec<_>>();
+        let nearby_bbs = nearby.iter().map(|&i| bbs[i]).collect::<Vec<_>>();
+        let current = nearby
+            .iter()
+            .copied()
+            .filter(|&i| bbs[i].collides_with_circle(*click, remote_radius))
+            .collect::<Vec<_>>();
+        let only_hit_by_click = current
+            .iter()
+            .copied()
+            .filter(|&i| hits[i] == 1)
+            .collect::<Vec<_>>();
+
+        let mut best = (current.len(), *click);
+        for dh in -JIGGLE_DISTANCE..=JIGGLE_DISTANCE {
+            for dw in -JIGGLE_DISTANCE..=JIGGLE_DISTANCE {
+                let test_click = Coord {
+                    w: (click.w + dw).clamp(0, w as i64),
+                    h: (click.h + dh).clamp(0, h as i64),
+                };
+                if !only_hit_by_click
+                    .iter()
+                    .all(|&i| bbs[i].collides_with_circle(test_click, remote_radius))
+                {
+                    continue;
+                }
+                let count = count_collisions_single(&nearby_bbs, remote_radius, test_click);
+                if count > best.0 {
+                    best = (count, test_click);
+                }
+            }
+        }
+        for &i in &current {
+            hits[i] -= 1;
+        }
+        *click = best.1;
+        for &i in &nearby {
+            if bbs[i].collides_with_circle(*click, remote_radius) {
+                hits[i] += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Module to listen for a global hotkey, so a shot can be fired while the game keeps focus
+
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use x11::xlib;
+
+/// A key and the modifiers that must be held with it, eg "ctrl+shift+F9"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hotkey {
+    /// X11 modifier mask
+    pub modifiers: u32,
+    /// X11 keysym name, eg "F9" or "space"
+    pub key: String,
+}
+
+/// Modifier names and their X11 masks
+const MODIFIERS: [(&str, u32); 4] = [
+    ("ctrl", xlib::ControlMask),
+    ("shift", xlib::ShiftMask),
+    ("alt", xlib::Mod1Mask),
+    ("super", xlib::Mod4Mask),
+];
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    /// Parses modifiers and a key joined by +, eg "ctrl+alt+F9"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().filter(|k| !k.is_empty());
+        let key = key.ok_or_else(|| format!("Hotkey {} has no key", s))?;
+        let mut modifiers = 0;
+        for part in parts {
+            let (_, mask) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown modifier {} in hotkey {}, expected ctrl, shift, alt or super",
+                        part, s
+                    )
+                })?;
+            modifiers |= mask;
+        }
+        Ok(Hotkey {
+            modifiers,
+            key: key.to_owned(),
+        })
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, mask) in MODIFIERS {
+            if self.modifiers & mask != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+impl From<Hotkey> for String {
+    fn from(hotkey: Hotkey) -> String {
+        hotkey.to_string()
+    }
+}
+
+/// Set by grab_error when XGrabKey fails, usually because another program has the same hotkey
+static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn grab_error(
+    _display: *mut xlib::Display,
+    _event: *mut xlib::XErrorEvent,
+) -> libc::c_int {
+    GRAB_FAILED.store(true, Ordering::SeqCst);
+    0
+}
+
+/// Grab hotkey for the whole X display, and call on_press from a background thread every time
+/// it is pressed, whichever window has focus.
+/// Returns once the key is grabbed, or with the reason it could not be.
+pub fn listen(hotkey: &Hotkey, on_press: impl Fn() + Send + 'static) -> Result<(), Box<dyn Error>> {
+    let hotkey = hotkey.clone();
+    let (grabbed_tx, grabbed_rx) = mpsc::channel();
+    std::thread::spawn(move || unsafe {
+        let display = match grab(&hotkey) {
+            Ok(display) => {
+                grabbed_tx.send(Ok(())).unwrap();
+                display
+            }
+            Err(e) => {
+                grabbed_tx.send(Err(e)).unwrap();
+                return;
+            }
+        };
+        let mut event: xlib::XEvent = std::mem::zeroed();
+        loop {
+            xlib::XNextEvent(display, &mut event);
+            if event.get_type() == xlib::KeyPress {
+                on_press();
+            }
+        }
+    });
+    Ok(grabbed_rx.recv()??)
+}
+
+/// Open a display and grab hotkey on its root window
+unsafe fn grab(hotkey: &Hotkey) -> Result<*mut xlib::Display, String> {
+    let display = xlib::XOpenDisplay(std::ptr::null());
+    if display.is_null() {
+        return Err("Could not open the X display".to_owned());
+    }
+    let name = CString::new(hotkey.key.as_str()).map_err(|e| e.to_string())?;
+    let keysym = xlib::XStringToKeysym(name.as_ptr());
+    let keycode = if keysym == 0 {
+        0
+    } else {
+        xlib::XKeysymToKeycode(display, keysym)
+    };
+    if keycode == 0 {
+        xlib::XCloseDisplay(display);
+        return Err(format!("Unknown key {} in hotkey {}", hotkey.key, hotkey));
+    }
+
+    let root = xlib::XDefaultRootWindow(display);
+    GRAB_FAILED.store(false, Ordering::SeqCst);
+    let old_handler = xlib::XSetErrorHandler(Some(grab_error));
+    // Caps lock and num lock are modifiers too, so grab with every combination of them
+    for locks in [
+        0,
+        xlib::LockMask,
+        xlib::Mod2Mask,
+        xlib::LockMask | xlib::Mod2Mask,
+    ] {
+        xlib::XGrabKey(
+            display,
+            keycode as i32,
+            hotkey.modifiers | locks,
+            root,
+            xlib::True,
+            xlib::GrabModeAsync,
+            xlib::GrabModeAsync,
+        );
+    }
+    // Errors are only reported once the server has processed the grabs
+    xlib::XSync(display, xlib::False);
+    xlib::XSetErrorHandler(old_handler);
+    if GRAB_FAILED.load(Ordering::SeqCst) {
+        xlib::XCloseDisplay(display);
+        return Err(format!(
+            "Could not grab hotkey {}, another program may be using it",
+            hotkey
+        ));
+    }
+    Ok(display)
+}
@@ -0,0 +1,287 @@
+//! Module for the backends that send artillery clicks to the game
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::screen::Coord;
+
+/// Something that can click the screen
+pub trait ClickSink {
+    /// Move the mouse to each click in order and press the left button
+    fn click(&mut self, clicks: &[Coord]) -> Result<(), Box<dyn Error>>;
+}
+
+/// Which `ClickSink` to use, selectable from the config file or command line
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickBackend {
+    /// Run the xdotool command
+    #[default]
+    Xdotool,
+    /// Fake input events with the X11 XTest extension
+    Xtest,
+    /// Create a virtual absolute pointer with /dev/uinput. Works under Wayland
+    Uinput,
+    /// Don't click anything, only append the clicks to a file
+    Record(PathBuf),
+}
+
+impl FromStr for ClickBackend {
+    type Err = String;
+
+    /// Parses xdotool, xtest, uinput or record:<path>
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xdotool" => Ok(ClickBackend::Xdotool),
+            "xtest" => Ok(ClickBackend::Xtest),
+            "uinput" => Ok(ClickBackend::Uinput),
+            _ => match s.strip_prefix("record:") {
+                Some(path) => Ok(ClickBackend::Record(path.into())),
+                None => Err(format!(
+                    "Unknown click backend {}, expected xdotool, xtest, uinput or record:<path>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl ClickBackend {
+    /// Create the sink for this backend
+    /// w: width of the screen in pixels (used to scale uinput coordinates)
+    /// h: height of the screen in pixels (used to scale uinput coordinates)
+    pub fn open(&self, w: u32, h: u32) -> Result<Box<dyn ClickSink>, Box<dyn Error>> {
+        Ok(match self {
+            ClickBackend::Xdotool => Box::new(Xdotool),
+            ClickBackend::Xtest => Box::new(XTest::open()?),
+            ClickBackend::Uinput => Box::new(Uinput::open(w, h)?),
+            ClickBackend::Record(path) => Box::new(Record::open(path.clone())?),
+        })
+    }
+}
+
+pub struct Xdotool;
+
+impl ClickSink for Xdotool {
+    fn click(&mut self, clicks: &[Coord]) -> Result<(), Box<dyn Error>> {
+        if clicks.is_empty() {
+            // xdotool fails when given no commands
+            return Ok(());
+        }
+        let mut cmd = String::new();
+        for click in clicks {
+            cmd.push_str(&format!("mousemove {} {} click 1 ", click.w, click.h));
+        }
+
+        let output = std::process::Command::new("xdotool")
+            .args(cmd.split_whitespace())
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "xdotool failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+pub struct XTest {
+    display: *mut x11::xlib::Display,
+}
+
+impl XTest {
+    pub fn open() -> Result<Self, Box<dyn Error>> {
+        let display = unsafe { x11::xlib::XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            return Err("Could not open the X display".into());
+        }
+        Ok(XTest { display })
+    }
+}
+
+impl ClickSink for XTest {
+    fn click(&mut self, clicks: &[Coord]) -> Result<(), Box<dyn Error>> {
+        use x11::xtest::{XTestFakeButtonEvent, XTestFakeMotionEvent};
+        const LEFT_BUTTON: u32 = 1;
+        for click in clicks {
+            unsafe {
+                // Screen -1 is the screen the pointer is currently on
+                XTestFakeMotionEvent(self.display, -1, click.w as i32, click.h as i32, 0);
+                XTestFakeButtonEvent(self.display, LEFT_BUTTON, 1, 0);
+                XTestFakeButtonEvent(self.display, LEFT_BUTTON, 0, 0);
+            }
+        }
+        unsafe {
+            x11::xlib::XSync(self.display, 0);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for XTest {
+    fn drop(&mut self) {
+        unsafe {
+            x11::xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+/// Virtual absolute pointer device, with an axis range matching the screen size
+pub struct Uinput {
+    file: File,
+}
+
+// Constants from linux/input-event-codes.h and linux/uinput.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0;
+const BTN_LEFT: u16 = 0x110;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_CNT: usize = 0x40;
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_SET_EVBIT: libc::c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x4004_5565;
+const UI_SET_ABSBIT: libc::c_ulong = 0x4004_5567;
+
+/// Time to let the compositor pick up the new device before sending events
+const UINPUT_SETTLE_TIME: Duration = Duration::from_millis(500);
+/// Time between events, so the game sees the pointer move before the button press
+const UINPUT_EVENT_DELAY: Duration = Duration::from_millis(5);
+
+#[repr(C)]
+struct UinputUserDev {
+    name: [u8; 80],
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+    ff_effects_max: u32,
+    absmax: [i32; ABS_CNT],
+    absmin: [i32; ABS_CNT],
+    absfuzz: [i32; ABS_CNT],
+    absflat: [i32; ABS_CNT],
+}
+
+#[repr(C)]
+struct InputEvent {
+    time: libc::timeval,
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+/// View a repr(C) struct as the bytes the kernel expects
+fn as_bytes<T>(t: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(t as *const T as *const u8, std::mem::size_of::<T>()) }
+}
+
+impl Uinput {
+    pub fn open(w: u32, h: u32) -> Result<Self, Box<dyn Error>> {
+        use std::os::unix::io::AsRawFd;
+        let mut file = OpenOptions::new().write(true).open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+        let ioctl = |request: libc::c_ulong, arg: libc::c_int| -> Result<(), Box<dyn Error>> {
+            if unsafe { libc::ioctl(fd, request, arg) } < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(())
+        };
+        ioctl(UI_SET_EVBIT, EV_KEY as libc::c_int)?;
+        ioctl(UI_SET_EVBIT, EV_ABS as libc::c_int)?;
+        ioctl(UI_SET_EVBIT, EV_SYN as libc::c_int)?;
+        ioctl(UI_SET_KEYBIT, BTN_LEFT as libc::c_int)?;
+        ioctl(UI_SET_ABSBIT, ABS_X as libc::c_int)?;
+        ioctl(UI_SET_ABSBIT, ABS_Y as libc::c_int)?;
+
+        let mut dev = UinputUserDev {
+            name: [0; 80],
+            bustype: 0x03, // BUS_USB
+            vendor: 0x1234,
+            product: 0x5678,
+            version: 1,
+            ff_effects_max: 0,
+            absmax: [0; ABS_CNT],
+            absmin: [0; ABS_CNT],
+            absfuzz: [0; ABS_CNT],
+            absflat: [0; ABS_CNT],
+        };
+        let name = b"Iron Dome virtual pointer";
+        dev.name[..name.len()].copy_from_slice(name);
+        dev.absmax[ABS_X as usize] = w.saturating_sub(1) as i32;
+        dev.absmax[ABS_Y as usize] = h.saturating_sub(1) as i32;
+        file.write_all(as_bytes(&dev))?;
+        ioctl(UI_DEV_CREATE, 0)?;
+        std::thread::sleep(UINPUT_SETTLE_TIME);
+        Ok(Uinput { file })
+    }
+
+    fn emit(&mut self, kind: u16, code: u16, value: i32) -> Result<(), Box<dyn Error>> {
+        let event = InputEvent {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            kind,
+            code,
+            value,
+        };
+        self.file.write_all(as_bytes(&event))?;
+        Ok(())
+    }
+}
+
+impl ClickSink for Uinput {
+    fn click(&mut self, clicks: &[Coord]) -> Result<(), Box<dyn Error>> {
+        for click in clicks {
+            self.emit(EV_ABS, ABS_X, click.w as i32)?;
+            self.emit(EV_ABS, ABS_Y, click.h as i32)?;
+            self.emit(EV_SYN, SYN_REPORT, 0)?;
+            std::thread::sleep(UINPUT_EVENT_DELAY);
+            self.emit(EV_KEY, BTN_LEFT, 1)?;
+            self.emit(EV_SYN, SYN_REPORT, 0)?;
+            self.emit(EV_KEY, BTN_LEFT, 0)?;
+            self.emit(EV_SYN, SYN_REPORT, 0)?;
+            std::thread::sleep(UINPUT_EVENT_DELAY);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Uinput {
+    fn drop(&mut self) {
+        use std::os::unix::io::AsRawFd;
+        unsafe {
+            libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY);
+        }
+    }
+}
+
+/// Appends each set of clicks to a file as one line of JSON, without clicking anything
+pub struct Record {
+    file: File,
+}
+
+impl Record {
+    pub fn open(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Record { file })
+    }
+}
+
+impl ClickSink for Record {
+    fn click(&mut self, clicks: &[Coord]) -> Result<(), Box<dyn Error>> {
+        writeln!(self.file, "{}", serde_json::to_string(clicks)?)?;
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::color::ColorClassifier;
 use crate::BoundingBox;
 use crate::Coord;
 use image::Rgb;
@@ -7,30 +8,80 @@ use std::time::Instant;
 
 /// Tries to generate bounding boxes of enemies based on a screenshot of the map view of the game.
 /// Works better the more zoomed in you are.
-pub fn process_red(i: &mut RgbImage) -> (Vec<BoundingBox>, i64) {
-    let mut bbs = scan_rects(i, 3);
+pub fn process_red(i: &mut RgbImage, classifier: &ColorClassifier) -> (Vec<BoundingBox>, i64) {
+    let (stages, spawner_width) = process_red_stages(i, classifier);
+    let bbs = stages.into_iter().last().map(|s| s.bbs).unwrap_or_default();
+    (bbs, spawner_width)
+}
+
+/// Bounding boxes found by a single scan of process_red
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub name: &'static str,
+    pub bbs: Vec<BoundingBox>,
+}
+
+/// The same as process_red, but keeps the bounding boxes found by each scan separate.
+/// The last stage holds every bounding box that survived the final filter.
+pub fn process_red_stages(i: &mut RgbImage, classifier: &ColorClassifier) -> (Vec<Stage>, i64) {
+    let mut stages = vec![];
+    let bbs = scan_rects(i, 3, classifier);
     let spawner_bb = deduce_spawner_size(&bbs);
     println!("Deduced w {}", spawner_bb.w());
-    bbs.extend(scan_rects_of_size(i, &spawner_bb));
-    bbs.extend(scan_rects(i, 1));
-    bbs.extend(scan_isolated_rects(i, &spawner_bb));
-    bbs.extend(scan_rect_any_ratio(i, 3));
+    stages.push(Stage {
+        name: "scan_rects",
+        bbs,
+    });
+    stages.push(Stage {
+        name: "scan_rects_of_size",
+        bbs: scan_rects_of_size(i, &spawner_bb, classifier),
+    });
+    stages.push(Stage {
+        name: "scan_rects_single_pass",
+        bbs: scan_rects(i, 1, classifier),
+    });
+    stages.push(Stage {
+        name: "scan_isolated_rects",
+        bbs: scan_isolated_rects(i, &spawner_bb, classifier),
+    });
+    stages.push(Stage {
+        name: "scan_rect_any_ratio",
+        bbs: scan_rect_any_ratio(i, 3, classifier),
+    });
+    let mut bbs = stages
+        .iter()
+        .flat_map(|s| s.bbs.iter().copied())
+        .collect::<Vec<_>>();
     bbs.retain(|bb| bb.area() as f64 > spawner_bb.area() as f64 / 5.0);
-    (bbs, spawner_bb.w())
+    stages.push(Stage {
+        name: "final_filter",
+        bbs,
+    });
+    (stages, spawner_bb.w())
 }
 
-/// Get the pixel or return a default pixel one tick from pure black
-fn get_pixel_default(img: &RgbImage, w: i64, h: i64) -> &Rgb<u8> {
-    get_pixel_checked(img, w, h).unwrap_or(&Rgb([1; 3]))
+/// Colour mask_bb paints over the bbs that were found
+const MASK_COLOR: Rgb<u8> = Rgb([0xff; 3]);
+
+/// Whether the pixel is in the image, not masked, and looks like an enemy.
+/// Masked pixels never look like enemies, so classifiers that take in the mask colour still
+/// can't find the same bb twice
+fn is_enemy(img: &RgbImage, w: i64, h: i64, classifier: &ColorClassifier) -> bool {
+    get_pixel_checked(img, w, h)
+        .is_some_and(|px| *px != MASK_COLOR && classifier.looks_like_enemy(px))
 }
 
-fn scan_rects_of_size(img: &mut RgbImage, template: &BoundingBox) -> Vec<BoundingBox> {
+fn scan_rects_of_size(
+    img: &mut RgbImage,
+    template: &BoundingBox,
+    classifier: &ColorClassifier,
+) -> Vec<BoundingBox> {
     let mut bbs = vec![];
     for _pass in 0..=1 {
         for h in 0..img.dimensions().1 as i64 {
             for w in 0..img.dimensions().0 as i64 {
-                if at_enemy_edge(img, w, h) {
-                    let bb = scan_single_bb(&img, w, h);
+                if at_enemy_edge(img, w, h, classifier) {
+                    let bb = scan_single_bb(&img, w, h, classifier);
                     if bb.w() >= template.w() && bb.h() >= template.h() {
                         // The template fits within this area
                         let new_bb = BoundingBox::new(bb.left_top, template.w(), template.h());
@@ -38,8 +89,8 @@ fn scan_rects_of_size(img: &mut RgbImage, template: &BoundingBox) -> Vec<Boundin
                         bbs.push(new_bb);
                     } else if i64::abs((bb.w() + 1) - template.w()) < 2 {
                         // Width is within 1 pixel of expectation.
-                        if *get_pixel_default(&img, bb.right_bottom.w, bb.right_bottom.h + 1)
-                            == Rgb([0, 0, 0])
+                        if get_pixel_checked(img, bb.right_bottom.w, bb.right_bottom.h + 1)
+                            == Some(&Rgb([0, 0, 0]))
                         {
                             // One pixel south is exactly pure black
                             // So we are probably on the edge of explored area
@@ -48,18 +99,25 @@ fn scan_rects_of_size(img: &mut RgbImage, template: &BoundingBox) -> Vec<Boundin
                             bbs.push(new_bb);
                         }
                     }
-                    if !has_well_defined_corners(img, &bb) {
+                    if !has_well_defined_corners(img, &bb, classifier) {
                         if i64::abs((bb.w() + 1) - template.w()) < 2
                             || i64::abs((bb.h() + 1) - template.h()) < 2
                         {
                             // width or height would fit one of these
-                            if get_corner_strict(img, bb.left_top.w, bb.left_top.h).is_some() {
+                            if get_corner_strict(img, bb.left_top.w, bb.left_top.h, classifier)
+                                .is_some()
+                            {
                                 let new_bb =
                                     BoundingBox::new(bb.left_top, template.w(), template.h());
                                 mask_bb(img, &new_bb);
                                 bbs.push(new_bb);
-                            } else if get_corner_strict(img, bb.right_bottom.w, bb.right_bottom.h)
-                                .is_some()
+                            } else if get_corner_strict(
+                                img,
+                                bb.right_bottom.w,
+                                bb.right_bottom.h,
+                                classifier,
+                            )
+                            .is_some()
                             {
                                 // We want to add such that the bb left bottom corner matches up
                                 let new_lefttop = Coord {
@@ -85,9 +143,9 @@ fn scan_rects_of_size(img: &mut RgbImage, template: &BoundingBox) -> Vec<Boundin
     bbs
 }
 
-fn at_enemy_edge(img: &RgbImage, w: i64, h: i64) -> bool {
-    if looks_like_enemy(get_pixel_default(&img, w, h)) {
-        if !looks_like_enemy(get_pixel_default(&img, w - 1, h)) {
+fn at_enemy_edge(img: &RgbImage, w: i64, h: i64, classifier: &ColorClassifier) -> bool {
+    if is_enemy(img, w, h, classifier) {
+        if !is_enemy(img, w - 1, h, classifier) {
             // We are introduced to a enemy pixel, and the previous pixel is not an enemy
             return true;
         }
@@ -95,10 +153,15 @@ fn at_enemy_edge(img: &RgbImage, w: i64, h: i64) -> bool {
     false
 }
 
-fn scan_single_bb_vert(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
+fn scan_single_bb_vert(
+    img: &RgbImage,
+    w: i64,
+    h: i64,
+    classifier: &ColorClassifier,
+) -> BoundingBox {
     let mut scanlength = 1;
-    for i in 1.. {
-        if looks_like_enemy(get_pixel_default(&img, w, h + i)) {
+    for i in 1..img.height() as i64 {
+        if is_enemy(img, w, h + i, classifier) {
             scanlength = i;
         } else {
             break;
@@ -107,9 +170,9 @@ fn scan_single_bb_vert(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
 
     let mut columns = 0;
 
-    'outer: for i in 1.. {
+    'outer: for i in 1..img.width() as i64 {
         for j in 0..scanlength {
-            if !looks_like_enemy(get_pixel_default(&img, w + i, h + j)) {
+            if !is_enemy(img, w + i, h + j, classifier) {
                 break 'outer;
             }
         }
@@ -119,10 +182,10 @@ fn scan_single_bb_vert(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
     BoundingBox::new(Coord { w, h }, columns, scanlength)
 }
 
-fn scan_single_bb(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
+fn scan_single_bb(img: &RgbImage, w: i64, h: i64, classifier: &ColorClassifier) -> BoundingBox {
     let mut scanlength = 1;
-    for i in 1.. {
-        if looks_like_enemy(get_pixel_default(&img, w + i, h)) {
+    for i in 1..img.width() as i64 {
+        if is_enemy(img, w + i, h, classifier) {
             scanlength = i;
         } else {
             break;
@@ -132,9 +195,9 @@ fn scan_single_bb(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
     let mut rows_above = 0;
     let mut rows_below = 0;
 
-    'outer: for i in 1.. {
+    'outer: for i in 1..img.height() as i64 {
         for j in 0..scanlength {
-            if !looks_like_enemy(get_pixel_default(&img, w + j, h - i)) {
+            if !is_enemy(img, w + j, h - i, classifier) {
                 break 'outer;
             }
         }
@@ -142,9 +205,9 @@ fn scan_single_bb(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
     }
 
     // scan below
-    'outer2: for i in 1.. {
+    'outer2: for i in 1..img.height() as i64 {
         for j in 0..scanlength {
-            if !looks_like_enemy(get_pixel_default(&img, w + j, h + i)) {
+            if !is_enemy(img, w + j, h + i, classifier) {
                 break 'outer2;
             }
         }
@@ -164,18 +227,23 @@ fn scan_single_bb(img: &RgbImage, w: i64, h: i64) -> BoundingBox {
 }
 
 /// Scan for isolated rectangles, and if any are too long or tall for a spawner template then try to split the area
-fn scan_isolated_rects(img: &mut RgbImage, template: &BoundingBox) -> Vec<BoundingBox> {
+fn scan_isolated_rects(
+    img: &mut RgbImage,
+    template: &BoundingBox,
+    classifier: &ColorClassifier,
+) -> Vec<BoundingBox> {
     let mut bbs = vec![];
     for h in 0..img.dimensions().1 as i64 {
         for w in 0..img.dimensions().0 as i64 {
-            if at_enemy_edge(&img, w, h) {
-                let bb = scan_single_bb(&img, w, h);
-                let perfect = get_corner(img, bb.left_top.w, bb.left_top.h)
+            if at_enemy_edge(&img, w, h, classifier) {
+                let bb = scan_single_bb(&img, w, h, classifier);
+                let perfect = get_corner(img, bb.left_top.w, bb.left_top.h, classifier)
                     == Some(Corner::LeftTop)
-                    && get_corner(img, bb.left_top.w, bb.right_bottom.h)
+                    && get_corner(img, bb.left_top.w, bb.right_bottom.h, classifier)
                         == Some(Corner::LeftBottom)
-                    && get_corner(img, bb.right_bottom.w, bb.left_top.h) == Some(Corner::RightTop)
-                    && get_corner(img, bb.right_bottom.w, bb.right_bottom.h)
+                    && get_corner(img, bb.right_bottom.w, bb.left_top.h, classifier)
+                        == Some(Corner::RightTop)
+                    && get_corner(img, bb.right_bottom.w, bb.right_bottom.h, classifier)
                         == Some(Corner::RightBottom);
                 if perfect {
                     if bb.w() > template.w() + 2 {
@@ -201,13 +269,13 @@ fn scan_isolated_rects(img: &mut RgbImage, template: &BoundingBox) -> Vec<Boundi
     bbs
 }
 
-fn scan_rects(img: &mut RgbImage, passes: i64) -> Vec<BoundingBox> {
+fn scan_rects(img: &mut RgbImage, passes: i64, classifier: &ColorClassifier) -> Vec<BoundingBox> {
     let mut bbs = vec![];
     for pass in 0..passes {
         for h in 0..img.dimensions().1 as i64 {
             for w in 0..img.dimensions().0 as i64 {
-                if at_enemy_edge(&img, w, h) {
-                    let bb = scan_single_bb(&img, w, h);
+                if at_enemy_edge(&img, w, h, classifier) {
+                    let bb = scan_single_bb(&img, w, h, classifier);
                     if bb.ratio() >= 1.25 && bb.ratio() <= 1.5 {
                         // Ratio appears close to what a spawner could be
                         mask_bb(img, &bb);
@@ -223,7 +291,7 @@ fn scan_rects(img: &mut RgbImage, passes: i64) -> Vec<BoundingBox> {
                                 bbs.push(bb);
                             }
                         }
-                        if has_well_defined_corners(img, &bb) {
+                        if has_well_defined_corners(img, &bb, classifier) {
                             if bb.ratio() / 2.0 <= 1.20 && bb.ratio() / 2.0 >= 0.7 {
                                 // Appears to be 2 worms side by side
                                 let new_bb = BoundingBox::new(bb.left_top, bb.w() / 2, bb.h());
@@ -247,14 +315,18 @@ fn scan_rects(img: &mut RgbImage, passes: i64) -> Vec<BoundingBox> {
 
 /// Final cleanup to try to find anything left, scaning both horizonal and vertical
 /// and prioritizing larger bb
-fn scan_rect_any_ratio(img: &mut RgbImage, passes: i64) -> Vec<BoundingBox> {
+fn scan_rect_any_ratio(
+    img: &mut RgbImage,
+    passes: i64,
+    classifier: &ColorClassifier,
+) -> Vec<BoundingBox> {
     let mut bbs = vec![];
     for _ in 0..passes {
         for h in 0..img.dimensions().1 as i64 {
             for w in 0..img.dimensions().0 as i64 {
-                if at_enemy_edge(&img, w, h) {
-                    let bb = scan_single_bb(&img, w, h);
-                    let other_bb = scan_single_bb_vert(&img, w, h);
+                if at_enemy_edge(&img, w, h, classifier) {
+                    let bb = scan_single_bb(&img, w, h, classifier);
+                    let other_bb = scan_single_bb_vert(&img, w, h, classifier);
                     if bb.area() > other_bb.area() {
                         mask_bb(img, &bb);
                         bbs.push(bb);
@@ -269,7 +341,8 @@ fn scan_rect_any_ratio(img: &mut RgbImage, passes: i64) -> Vec<BoundingBox> {
     bbs
 }
 
-fn deduce_spawner_size(bbs: &[BoundingBox]) -> BoundingBox {
+/// Guess the size of a spawner from the most common area of the spawner shaped bbs
+pub fn deduce_spawner_size(bbs: &[BoundingBox]) -> BoundingBox {
     let mut bbs = bbs.to_owned();
     bbs.sort_by_key(|bb| bb.area());
     bbs.retain(|bb| bb.ratio() >= 1.25 && bb.ratio() <= 1.5);
@@ -308,13 +381,6 @@ fn deduce_spawner_size(bbs: &[BoundingBox]) -> BoundingBox {
         .unwrap_or(&BoundingBox::default())
 }
 
-fn looks_like_enemy(px: &Rgb<u8>) -> bool {
-    let r = px[0];
-    let g = px[1];
-    let b = px[2];
-    r > 150 && g < 35 && b < 36 && g > 11 && b > 14
-}
-
 fn get_pixel_checked(img: &RgbImage, w: i64, h: i64) -> Option<&Rgb<u8>> {
     if w < 0 || h < 0 {
         return None;
@@ -333,12 +399,7 @@ fn mask_bb(img: &mut RgbImage, bb: &BoundingBox) {
         if w as u32 >= img.width() || h as u32 >= img.height() {
             continue;
         }
-        let mut px = *img.get_pixel(w as u32, h as u32);
-        // Add some green so this no longer looks like an enemy
-        px[0] = 0xff;
-        px[1] = 0xff;
-        px[2] = 0xff;
-        img.put_pixel(w as u32, h as u32, px);
+        img.put_pixel(w as u32, h as u32, MASK_COLOR);
     }
 }
 
@@ -350,16 +411,25 @@ enum Corner {
     RightBottom,
 }
 
-fn has_well_defined_corners(img: &RgbImage, bb: &BoundingBox) -> bool {
-    return get_corner_strict(&img, bb.left_top.w, bb.left_top.h).is_some()
-        && get_corner_strict(&img, bb.left_top.w, bb.right_bottom.h).is_some()
-        && get_corner_strict(&img, bb.right_bottom.w, bb.left_top.h).is_some()
-        && get_corner_strict(&img, bb.right_bottom.w, bb.right_bottom.h).is_some();
+fn has_well_defined_corners(
+    img: &RgbImage,
+    bb: &BoundingBox,
+    classifier: &ColorClassifier,
+) -> bool {
+    return get_corner_strict(&img, bb.left_top.w, bb.left_top.h, classifier).is_some()
+        && get_corner_strict(&img, bb.left_top.w, bb.right_bottom.h, classifier).is_some()
+        && get_corner_strict(&img, bb.right_bottom.w, bb.left_top.h, classifier).is_some()
+        && get_corner_strict(&img, bb.right_bottom.w, bb.right_bottom.h, classifier).is_some();
 }
 
 /// The same as get_corner, but enforces that all corners are NOT bordering a fully black pixel
-fn get_corner_strict(img: &RgbImage, w: i64, h: i64) -> Option<Corner> {
-    if let Some(corn) = get_corner(img, w, h) {
+fn get_corner_strict(
+    img: &RgbImage,
+    w: i64,
+    h: i64,
+    classifier: &ColorClassifier,
+) -> Option<Corner> {
+    if let Some(corn) = get_corner(img, w, h, classifier) {
         match corn {
             Corner::LeftTop => {
                 if get_pixel_checked(img, w, h - 1) == Some(&Rgb([0; 3]))
@@ -398,26 +468,20 @@ fn get_corner_strict(img: &RgbImage, w: i64, h: i64) -> Option<Corner> {
     None
 }
 
-fn get_corner(img: &RgbImage, w: i64, h: i64) -> Option<Corner> {
-    if let Some(px) = get_pixel_checked(img, w, h) {
-        if looks_like_enemy(px) {
-            let p1 = get_pixel_checked(img, w - 1, h);
-            let p2 = get_pixel_checked(img, w, h - 1);
-            let p3 = get_pixel_checked(img, w + 1, h);
-            let p4 = get_pixel_checked(img, w, h + 1);
-            let p1 = p1.is_some() && looks_like_enemy(p1.unwrap());
-            let p2 = p2.is_some() && looks_like_enemy(p2.unwrap());
-            let p3 = p3.is_some() && looks_like_enemy(p3.unwrap());
-            let p4 = p4.is_some() && looks_like_enemy(p4.unwrap());
-            if p1 && p2 && !p3 && !p4 {
-                return Some(Corner::RightBottom);
-            } else if !p1 && p2 && p3 && !p4 {
-                return Some(Corner::LeftBottom);
-            } else if !p1 && !p2 && p3 && p4 {
-                return Some(Corner::LeftTop);
-            } else if p1 && !p2 && !p3 && p4 {
-                return Some(Corner::RightTop);
-            }
+fn get_corner(img: &RgbImage, w: i64, h: i64, classifier: &ColorClassifier) -> Option<Corner> {
+    if is_enemy(img, w, h, classifier) {
+        let p1 = is_enemy(img, w - 1, h, classifier);
+        let p2 = is_enemy(img, w, h - 1, classifier);
+        let p3 = is_enemy(img, w + 1, h, classifier);
+        let p4 = is_enemy(img, w, h + 1, classifier);
+        if p1 && p2 && !p3 && !p4 {
+            return Some(Corner::RightBottom);
+        } else if !p1 && p2 && p3 && !p4 {
+            return Some(Corner::LeftBottom);
+        } else if !p1 && !p2 && p3 && p4 {
+            return Some(Corner::LeftTop);
+        } else if p1 && !p2 && !p3 && p4 {
+            return Some(Corner::RightTop);
         }
     }
 
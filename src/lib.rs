@@ -0,0 +1,723 @@
+//! Detection and targeting for the Factorio artillery clicker
+//!
+//! `detect` turns a screenshot of the map view into a list of enemy bounding boxes,
+//! and `plan` turns a list of bounding boxes into artillery remote clicks.
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+pub mod capture;
+
+pub mod color;
+use color::ColorClassifier;
+
+pub mod components;
+use components::process_red_components;
+
+pub mod config;
+use config::Config;
+use config::ExclusionZone;
+
+pub mod debug;
+use debug::find_spawner_positions;
+use debug::find_worm_positions;
+use debug::remap_positions_to_bb;
+
+pub mod draw;
+
+pub mod evaluate;
+
+pub mod generator;
+use generator::gen_clicks_from_targets_fixed;
+use generator::gen_clicks_from_targets_rand;
+use generator::order_clicks;
+use generator::refine_clicks;
+pub use generator::PlannerConfig;
+
+pub mod hotkey;
+
+pub mod input;
+
+pub mod legit;
+use legit::process_red;
+
+pub mod report;
+
+pub mod screen;
+pub use screen::BoundingBox;
+pub use screen::Coord;
+
+pub mod solver;
+use solver::solve;
+use solver::Solution;
+use solver::SolverOptions;
+
+pub mod synthetic;
+
+/// Radius in pixels of the artillery remote when zoomed in far enough to see debug circles
+pub const ARTY_REMOTE_RADIUS: u32 = 40;
+
+/// Which detectors `detect` is allowed to run
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectOptions {
+    /// Look for the blue/magenta show-active-state debug circles
+    pub scan_debug: bool,
+    /// Look for red pixels shaped like spawners/worms
+    pub scan_red: bool,
+    /// Which pixels the red detector sees as enemies
+    pub classifier: ColorClassifier,
+    /// How the red detector finds enemies in the enemy pixels
+    pub red_detector: RedDetector,
+}
+
+impl Default for DetectOptions {
+    fn default() -> Self {
+        DetectOptions {
+            scan_debug: true,
+            scan_red: true,
+            classifier: ColorClassifier::default(),
+            red_detector: RedDetector::default(),
+        }
+    }
+}
+
+/// Algorithm used by the red detector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedDetector {
+    /// `legit::process_red`, several scan-line passes that mask what they find
+    #[default]
+    Scan,
+    /// `components::process_red_components`, blobs of enemy pixels split into spawners
+    Components,
+}
+
+impl FromStr for RedDetector {
+    type Err = String;
+
+    /// Parses scan or components
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scan" => Ok(RedDetector::Scan),
+            "components" => Ok(RedDetector::Components),
+            _ => Err(format!(
+                "Unknown red detector {}, expected scan or components",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RedDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedDetector::Scan => write!(f, "scan"),
+            RedDetector::Components => write!(f, "components"),
+        }
+    }
+}
+
+/// The detector that produced a set of `Detections`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Detector {
+    Debug,
+    Red,
+    None,
+}
+
+/// What kind of enemy a bounding box is thought to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetClass {
+    Spawner,
+    Worm,
+    /// The scan red detector can't tell spawners and worms apart
+    Unknown,
+}
+
+impl TargetClass {
+    /// Order targets are shot in, highest first. Spawners keep making biters so they go first,
+    /// and an unknown target may be a spawner.
+    pub fn priority(self) -> u32 {
+        match self {
+            TargetClass::Spawner => 2,
+            TargetClass::Unknown => 1,
+            TargetClass::Worm => 0,
+        }
+    }
+}
+
+/// A bounding box to shoot, and how many shells it takes to kill
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub bb: BoundingBox,
+    pub class: TargetClass,
+    /// Shells that must hit the target
+    pub hits: u32,
+}
+
+impl Target {
+    /// A target of unknown class that one shell kills
+    pub fn unknown(bb: BoundingBox) -> Self {
+        Target {
+            bb,
+            class: TargetClass::Unknown,
+            hits: 1,
+        }
+    }
+}
+
+/// Shells needed to kill each class of target
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HitRequirements {
+    pub spawner: u32,
+    pub worm: u32,
+    pub unknown: u32,
+    /// Targets at least this many remote radii wide or high are big nests or worms,
+    /// and need `large` shells
+    pub large_size: Option<f64>,
+    pub large: u32,
+}
+
+impl Default for HitRequirements {
+    fn default() -> Self {
+        HitRequirements {
+            spawner: 1,
+            worm: 1,
+            unknown: 1,
+            large_size: None,
+            large: 2,
+        }
+    }
+}
+
+impl HitRequirements {
+    /// Shells needed to kill a target of class covering bb, when the remote has remote_radius
+    pub fn for_target(&self, bb: &BoundingBox, class: TargetClass, remote_radius: u32) -> u32 {
+        let hits = match class {
+            TargetClass::Spawner => self.spawner,
+            TargetClass::Worm => self.worm,
+            TargetClass::Unknown => self.unknown,
+        };
+        let size = bb.w().max(bb.h()) as f64;
+        match self.large_size {
+            Some(large_size) if size >= large_size * remote_radius as f64 => hits.max(self.large),
+            _ => hits,
+        }
+    }
+}
+
+/// Targets found in a single image
+#[derive(Debug, Clone)]
+pub struct Detections {
+    pub detector: Detector,
+    pub bbs: Vec<BoundingBox>,
+    /// The class of each bb in bbs
+    pub classes: Vec<TargetClass>,
+    /// Estimated artillery remote radius (in pixels) at the zoom level of the image
+    pub remote_radius: u32,
+    /// Width in pixels of a spawner, as deduced by the red detector
+    pub spawner_width: Option<i64>,
+}
+
+impl Detections {
+    /// No targets, because no detector was run
+    pub fn none() -> Self {
+        Detections {
+            detector: Detector::None,
+            bbs: vec![],
+            classes: vec![],
+            remote_radius: ARTY_REMOTE_RADIUS,
+            spawner_width: None,
+        }
+    }
+
+    /// Each bb with its class, and the shells it needs according to hits
+    pub fn targets(&self, hits: &HitRequirements) -> Vec<Target> {
+        self.bbs
+            .iter()
+            .zip(&self.classes)
+            .map(|(bb, &class)| Target {
+                bb: *bb,
+                class,
+                hits: hits.for_target(bb, class, self.remote_radius),
+            })
+            .collect()
+    }
+}
+
+/// Click generation algorithm used by `plan`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Generator {
+    /// `gen_clicks_from_targets_rand`
+    #[default]
+    Random,
+    /// `gen_clicks_from_targets_fixed`
+    Fixed,
+    /// `solver::solve`
+    Exact(SolverOptions),
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    /// Parses random, fixed or exact[:<time budget ms>]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Generator::Random),
+            "fixed" => Ok(Generator::Fixed),
+            "exact" => Ok(Generator::Exact(SolverOptions::default())),
+            _ => match s.strip_prefix("exact:").map(str::parse) {
+                Some(Ok(time_budget_ms)) => Ok(Generator::Exact(SolverOptions { time_budget_ms })),
+                _ => Err(format!(
+                    "Unknown generator {}, expected random, fixed or exact[:<time budget ms>]",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlanOptions {
+    /// Estimated size of the artillery remote target area (in pixels)
+    pub remote_radius: u32,
+    /// Width of the image in pixels (to keep generated clicks in bounds)
+    pub width: u32,
+    /// Height of the image in pixels (to keep generated clicks in bounds)
+    pub height: u32,
+    pub generator: Generator,
+    /// Run `refine_clicks` on the generated clicks
+    pub refine: bool,
+    pub safety: SafetyMargin,
+    /// Settings of the random generator
+    pub planner: PlannerConfig,
+}
+
+impl PlanOptions {
+    /// Options to plan clicks for `detections` found in `img`, using the random generator
+    pub fn for_detections(detections: &Detections, img: &RgbImage) -> Self {
+        PlanOptions {
+            remote_radius: detections.remote_radius,
+            width: img.width(),
+            height: img.height(),
+            generator: Generator::Random,
+            refine: true,
+            safety: SafetyMargin::default(),
+            planner: PlannerConfig::default(),
+        }
+    }
+}
+
+/// How much room `plan` leaves for shells that don't land exactly where they were aimed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafetyMargin {
+    /// Pixels taken off the remote radius, for the scatter of where shells land
+    pub dispersion: u32,
+    /// Pixels the blast must reach into each target, not just touch its edge
+    pub min_depth: u32,
+    /// Shells that must hit each target, for targets that survive one shell
+    pub min_shots: u32,
+}
+
+impl Default for SafetyMargin {
+    fn default() -> Self {
+        SafetyMargin {
+            dispersion: 0,
+            min_depth: 0,
+            min_shots: 1,
+        }
+    }
+}
+
+impl SafetyMargin {
+    /// The radius that shells can be relied on to hit
+    pub fn effective_radius(&self, remote_radius: u32) -> u32 {
+        remote_radius.saturating_sub(self.dispersion)
+    }
+
+    /// The part of bb that a click must reach, bb shrunk by min_depth down to its centre
+    pub fn target(&self, bb: &BoundingBox) -> BoundingBox {
+        let inset_w = (self.min_depth as i64).min(bb.w() / 2);
+        let inset_h = (self.min_depth as i64).min(bb.h() / 2);
+        BoundingBox {
+            left_top: Coord {
+                w: bb.left_top.w + inset_w,
+                h: bb.left_top.h + inset_h,
+            },
+            right_bottom: Coord {
+                w: bb.right_bottom.w - inset_w,
+                h: bb.right_bottom.h - inset_h,
+            },
+        }
+    }
+}
+
+/// Find enemies in a screenshot of the map view.
+/// The debug detector is preferred when it finds anything, otherwise the red detector is used.
+pub fn detect(img: &RgbImage, options: DetectOptions) -> Detections {
+    if options.scan_debug {
+        let (bbs, classes) = detect_debug(img)
+            .into_iter()
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        if !bbs.is_empty() || !options.scan_red {
+            return Detections {
+                detector: Detector::Debug,
+                bbs,
+                classes,
+                remote_radius: ARTY_REMOTE_RADIUS,
+                spawner_width: None,
+            };
+        }
+    }
+    if options.scan_red {
+        let (bbs, classes, spawner_width) = match options.red_detector {
+            RedDetector::Scan => {
+                let (bbs, spawner_width) = process_red(&mut img.clone(), &options.classifier);
+                let classes = vec![TargetClass::Unknown; bbs.len()];
+                (bbs, classes, spawner_width)
+            }
+            RedDetector::Components => process_red_components(img, &options.classifier),
+        };
+        return Detections {
+            detector: Detector::Red,
+            classes,
+            bbs,
+            remote_radius: red_remote_radius(spawner_width),
+            spawner_width: Some(spawner_width),
+        };
+    }
+    Detections::none()
+}
+
+/// Estimate the artillery remote radius from the width of a spawner deduced by the red detector
+pub fn red_remote_radius(spawner_width: i64) -> u32 {
+    (spawner_width as f64 * 0.43) as u32
+}
+
+fn detect_debug(img: &RgbImage) -> Vec<(BoundingBox, TargetClass)> {
+    let mut debug_img = img.clone();
+
+    let debug_spawner_positions = find_spawner_positions(&debug_img);
+    let debug_worm_positions = find_worm_positions(&debug_img);
+
+    let spawner_mask = BoundingBox {
+        left_top: Coord { w: -30, h: -11 },
+        right_bottom: Coord { w: 23, h: 31 },
+    };
+    let spawner_bbs =
+        remap_positions_to_bb(&debug_spawner_positions, &spawner_mask, &mut debug_img);
+
+    let worm_mask = BoundingBox {
+        left_top: Coord { w: -8, h: 1 },
+        right_bottom: Coord { w: 15, h: 22 },
+    };
+    let worm_bbs = remap_positions_to_bb(&debug_worm_positions, &worm_mask, &mut debug_img);
+    let mut combined_bbs = spawner_bbs
+        .into_iter()
+        .map(|bb| (bb, TargetClass::Spawner))
+        .collect::<Vec<_>>();
+    combined_bbs.extend(worm_bbs.into_iter().map(|bb| (bb, TargetClass::Worm)));
+    combined_bbs.sort_by(|(s, _), (other, _)| {
+        let res = if s.left_top.h == other.left_top.h {
+            s.left_top.w.cmp(&other.left_top.h)
+        } else {
+            s.left_top.h.cmp(&other.left_top.h)
+        };
+        res
+    });
+    combined_bbs
+}
+
+/// How one round of `plan_targets_with_stats` found its clicks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanStats {
+    /// Clicks from the generator
+    pub generated: usize,
+    /// Clicks each finished guess of the random generator found, fewest first, when it was the
+    /// generator
+    pub guesses: Vec<usize>,
+    /// What the exact solver found, when it was the generator
+    pub solution: Option<Solution>,
+    /// Clicks left after `refine_clicks`, when refining is on
+    pub refined: Option<usize>,
+    /// Time taken to generate and refine the clicks
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for PlanStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Generated {} clicks", self.generated)?;
+        if !self.guesses.is_empty() {
+            write!(f, ", guesses found {:?} clicks", self.guesses)?;
+        }
+        if let Some(solution) = &self.solution {
+            write!(
+                f,
+                " from {} candidate clicks, lower bound {}, gap {:.1}%",
+                solution.candidates,
+                solution.lower_bound,
+                solution.gap() * 100.
+            )?;
+        }
+        if let Some(refined) = self.refined {
+            write!(
+                f,
+                ", refinement saved {} shells ({} -> {} clicks)",
+                self.generated - refined,
+                self.generated,
+                refined
+            )?;
+        }
+        write!(f, " in {:?}", self.elapsed)
+    }
+}
+
+/// Generate artillery clicks that hit every bounding box
+pub fn plan(bbs: &[BoundingBox], options: PlanOptions) -> Vec<Coord> {
+    let targets = bbs.iter().copied().map(Target::unknown).collect::<Vec<_>>();
+    plan_targets(&targets, options)
+}
+
+/// Generate artillery clicks that hit every target with the shells it needs,
+/// ordered so the clicks on the highest priority targets are fired first
+pub fn plan_targets(targets: &[Target], options: PlanOptions) -> Vec<Coord> {
+    plan_targets_with_stats(targets, options).0
+}
+
+/// The same as plan_targets, but also returns how each round of planning went, one round for
+/// every shell the targets need
+pub fn plan_targets_with_stats(
+    targets: &[Target],
+    options: PlanOptions,
+) -> (Vec<Coord>, Vec<PlanStats>) {
+    let radius = options.safety.effective_radius(options.remote_radius);
+    let targets = targets
+        .iter()
+        .map(|target| Target {
+            bb: options.safety.target(&target.bb),
+            hits: target.hits.max(options.safety.min_shots),
+            ..*target
+        })
+        .collect::<Vec<_>>();
+    let (mut clicks, stats) = plan_once(&targets, radius, options);
+    let mut rounds = vec![stats];
+    // Plan again for the targets that still need more shells, until every target has enough
+    let most_hits = targets.iter().map(|target| target.hits).max().unwrap_or(0);
+    for _ in 1..most_hits {
+        let short = targets
+            .iter()
+            .filter(|target| {
+                let hits = clicks
+                    .iter()
+                    .filter(|click| target.bb.collides_with_circle(**click, radius))
+                    .count();
+                hits < target.hits as usize
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        if short.is_empty() {
+            break;
+        }
+        let (more, stats) = plan_once(&short, radius, options);
+        clicks.extend(more);
+        rounds.push(stats);
+    }
+    order_clicks(&mut clicks, &targets, radius);
+    (clicks, rounds)
+}
+
+/// Hit every target once with the generator from options, ignoring its safety margin
+fn plan_once(
+    targets: &[Target],
+    remote_radius: u32,
+    options: PlanOptions,
+) -> (Vec<Coord>, PlanStats) {
+    let it = Instant::now();
+    let bbs = targets.iter().map(|target| target.bb).collect::<Vec<_>>();
+    let bbs = &bbs[..];
+    let mut solution = None;
+    let mut guesses = vec![];
+    let clicks = match options.generator {
+        Generator::Random => {
+            let (clicks, counts) = gen_clicks_from_targets_rand(
+                targets,
+                remote_radius,
+                options.width,
+                options.height,
+                options.planner,
+            );
+            guesses = counts;
+            clicks
+        }
+        Generator::Fixed => {
+            gen_clicks_from_targets_fixed(targets, remote_radius, options.width, options.height)
+        }
+        Generator::Exact(solver_options) => {
+            let solved = solve(
+                bbs,
+                remote_radius,
+                options.width,
+                options.height,
+                solver_options,
+            );
+            let clicks = solved.clicks.clone();
+            solution = Some(solved);
+            clicks
+        }
+    };
+    let mut stats = PlanStats {
+        generated: clicks.len(),
+        guesses,
+        solution,
+        ..PlanStats::default()
+    };
+    let clicks = if options.refine {
+        let refined = refine_clicks(&clicks, bbs, remote_radius, options.width, options.height);
+        stats.refined = Some(refined.len());
+        refined
+    } else {
+        clicks
+    };
+    stats.elapsed = it.elapsed();
+    (clicks, stats)
+}
+
+/// Run the full detection and planning pipeline on a screenshot
+/// config: provides the exclusion zones that must not be clicked, and the click generator
+pub fn process_image_into_clicks(
+    img: RgbImage,
+    scan_debug: bool,
+    scan_red: bool,
+    config: &Config,
+) -> Vec<Coord> {
+    process_image(img, scan_debug, scan_red, config).1
+}
+
+/// The same as process_image_into_clicks, but also returns the detections the clicks were planned
+/// from, and how each round of planning went
+pub fn process_image(
+    mut img: RgbImage,
+    scan_debug: bool,
+    scan_red: bool,
+    config: &Config,
+) -> (Detections, Vec<Coord>, Vec<PlanStats>) {
+    let ui_scale = config.ui_scale_for(img.height());
+    mask_excluded_areas(&mut img, &config.exclusion_zones, ui_scale);
+    if scan_debug {
+        let options = DetectOptions {
+            scan_debug: true,
+            scan_red: false,
+            classifier: config.classifier(),
+            red_detector: config.red_detector,
+        };
+        let detections = detect(&img, options);
+        let (mut debug_clicks, stats) = plan_targets_with_stats(
+            &detections.targets(&config.hits),
+            PlanOptions {
+                generator: config.generator,
+                refine: config.refine,
+                safety: config.safety,
+                planner: config.planner,
+                ..PlanOptions::for_detections(&detections, &img)
+            },
+        );
+        remove_clicks_in_excluded_areas(
+            &mut debug_clicks,
+            &config.exclusion_zones,
+            img.width(),
+            img.height(),
+            ui_scale,
+        );
+        if !debug_clicks.is_empty() || !scan_red {
+            return (detections, debug_clicks, stats);
+        }
+    }
+    if scan_red {
+        let options = DetectOptions {
+            scan_debug: false,
+            scan_red: true,
+            classifier: config.classifier(),
+            red_detector: config.red_detector,
+        };
+        let detections = detect(&img, options);
+        let (mut red_clicks, stats) = plan_targets_with_stats(
+            &detections.targets(&config.hits),
+            PlanOptions {
+                generator: config.generator,
+                refine: config.refine,
+                safety: config.safety,
+                planner: config.planner,
+                ..PlanOptions::for_detections(&detections, &img)
+            },
+        );
+        remove_clicks_in_excluded_areas(
+            &mut red_clicks,
+            &config.exclusion_zones,
+            img.width(),
+            img.height(),
+            ui_scale,
+        );
+        return (detections, red_clicks, stats);
+    }
+    (Detections::none(), vec![], vec![])
+}
+
+/// Black out the zones that are marked as masked, so the detectors don't see them
+pub fn mask_excluded_areas(img: &mut RgbImage, zones: &[ExclusionZone], ui_scale: f64) {
+    let (w, h) = img.dimensions();
+    for zone in zones.iter().filter(|zone| zone.mask) {
+        let points = zone.to_pixels(w, h, ui_scale);
+        let min = |i: usize| points.iter().map(|p| p[i]).fold(f64::INFINITY, f64::min);
+        let max = |i: usize| {
+            points
+                .iter()
+                .map(|p| p[i])
+                .fold(f64::NEG_INFINITY, f64::max)
+        };
+        let left = min(0).floor().max(0.) as u32;
+        let top = min(1).floor().max(0.) as u32;
+        let right = max(0).ceil().min(w as f64 - 1.);
+        let bottom = max(1).ceil().min(h as f64 - 1.);
+        if right < 0. || bottom < 0. {
+            continue;
+        }
+        for y in top..=bottom as u32 {
+            for x in left..=right as u32 {
+                let px = Coord {
+                    w: x as i64,
+                    h: y as i64,
+                };
+                if zone.contains(px, w, h, ui_scale) {
+                    img.put_pixel(x, y, image::Rgb([0, 0, 0]));
+                }
+            }
+        }
+    }
+}
+
+/// Remove clicks that would land on the game UI instead of the map
+/// zones: areas of the screen that must not be clicked
+/// w: width of the screenshot in pixels
+/// h: height of the screenshot in pixels
+/// ui_scale: Factorio's GUI scale, for zones measured in UI pixels
+pub fn remove_clicks_in_excluded_areas(
+    clicks: &mut Vec<Coord>,
+    zones: &[ExclusionZone],
+    w: u32,
+    h: u32,
+    ui_scale: f64,
+) {
+    clicks.retain(|click| {
+        !zones
+            .iter()
+            .any(|zone| zone.contains(*click, w, h, ui_scale))
+    });
+}
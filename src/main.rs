@@ -1,236 +1,747 @@
-use crate::legit::process_red;
-use captrs::Capturer;
-use image::Rgb;
 use image::RgbImage;
 
 pub(crate) use image::io::Reader as ImageReader;
 
 use std::error::Error;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Instant;
 
-mod legit;
+use iron_dome::capture::CaptureBackend;
+use iron_dome::capture::Frame;
+use iron_dome::capture::FrameSource;
+use iron_dome::color::fit;
+use iron_dome::config::Config;
+use iron_dome::config::ExclusionZone;
+use iron_dome::config::DEFAULT_CONFIG_PATH;
+use iron_dome::config::VANILLA_COLOR_PROFILE;
+use iron_dome::draw::classifier_preview;
+use iron_dome::draw::red_stage_overlays;
+use iron_dome::evaluate::evaluate;
+use iron_dome::evaluate::Annotation;
+use iron_dome::evaluate::Evaluation;
+use iron_dome::hotkey;
+use iron_dome::input::ClickBackend;
+use iron_dome::input::ClickSink;
+use iron_dome::process_image;
+use iron_dome::report::OutputFormat;
+use iron_dome::report::Report;
+use iron_dome::solver::SolverOptions;
+use iron_dome::synthetic;
+use iron_dome::synthetic::SceneOptions;
+use iron_dome::Coord;
+use iron_dome::Detections;
+use iron_dome::Detector;
+use iron_dome::PlanStats;
+use iron_dome::RedDetector;
 
-mod debug;
-use debug::find_spawner_positions;
-use debug::find_worm_positions;
-use debug::remap_positions_to_bb;
+mod calibrate;
+use calibrate::ColorCalibrator;
 
-mod generator;
-use generator::gen_clicks_from_bbs_fixed;
-use generator::gen_clicks_from_bbs_rand;
+mod editor;
+use editor::ZoneEditor;
+use editor::EDITOR_WINDOW_SIZE;
 
-mod screen;
-use screen::BoundingBox;
-use screen::Coord;
+const WINDOW_SIZE: egui::Vec2 = egui::vec2(160., 285.);
+/// Window position is the outer corner but size is the inner size, so allow for a title bar
+/// and borders when excluding the window
+const WINDOW_DECORATION_SIZE: f32 = 40.;
 
-const SCREEN_W: i64 = 2560;
-const SCREEN_H: i64 = 1440;
-
-const ARTY_REMOTE_RADIUS: u32 = 40;
-
-#[derive(Debug)]
 struct Gui {
     scan_debug: bool,
     scan_red: bool,
+    config: Config,
+    config_path: String,
+    /// Most recent screenshot, shown in the zone editor
+    last_capture: Option<RgbImage>,
+    /// Some while editing exclusion zones
+    editor: Option<ZoneEditor>,
+    /// Some while calibrating the enemy colour, which uses the editor's window size
+    calibrator: Option<ColorCalibrator>,
+    /// Auto fire is on, see config.auto_fire
+    auto: bool,
+    next_scan: Instant,
+    /// Receives a message each time the global hotkey is pressed
+    hotkey: Option<Receiver<()>>,
+    /// Desktop pixels covered by this window, when known
+    window_rect: Option<(Coord, Coord)>,
+    /// Opened on the first screenshot, with the backend it was opened from
+    source: Option<(CaptureBackend, Box<dyn FrameSource>)>,
+    /// Opened on the first set of clicks
+    sink: Option<OpenSink>,
 }
 
-impl Default for Gui {
-    fn default() -> Self {
+/// A click sink with the backend and desktop size it was opened for, to tell when it needs
+/// reopening
+struct OpenSink {
+    backend: ClickBackend,
+    desktop_size: (u32, u32),
+    sink: Box<dyn ClickSink>,
+}
+
+impl Gui {
+    fn new(config: Config, config_path: String) -> Self {
         Gui {
             scan_debug: true,
             scan_red: true,
+            config,
+            config_path,
+            last_capture: None,
+            editor: None,
+            calibrator: None,
+            auto: false,
+            next_scan: Instant::now(),
+            hotkey: None,
+            window_rect: None,
+            source: None,
+            sink: None,
+        }
+    }
+
+    /// Remember where this window is, so it can be left out of screenshots and clicks
+    fn track_window(&mut self, ctx: &egui::Context, frame: &eframe::Frame) {
+        let info = &frame.info().window_info;
+        let scale = ctx.pixels_per_point();
+        self.window_rect = info.position.map(|position| {
+            let left_top = position.to_vec2() * scale;
+            let right_bottom = left_top + info.size * scale;
+            (
+                Coord {
+                    w: left_top.x.floor() as i64,
+                    h: left_top.y.floor() as i64,
+                },
+                Coord {
+                    w: (right_bottom.x + WINDOW_DECORATION_SIZE).ceil() as i64,
+                    h: (right_bottom.y + WINDOW_DECORATION_SIZE).ceil() as i64,
+                },
+            )
+        });
+    }
+
+    /// The config with this window added as an exclusion zone of the frame
+    fn config_for(&self, frame: &Frame) -> Config {
+        let mut config = self.config.clone();
+        if let Some((left_top, right_bottom)) = self.window_rect {
+            let to_frame = |c: Coord| Coord {
+                w: c.w - frame.origin.w,
+                h: c.h - frame.origin.h,
+            };
+            config.exclusion_zones.push(ExclusionZone::window(
+                "Iron Dome",
+                to_frame(left_top),
+                to_frame(right_bottom),
+            ));
+        }
+        config
+    }
+
+    /// Grab the hotkey from the config, if there is one
+    fn listen_for_hotkey(&mut self, ctx: &egui::Context) {
+        let hotkey = match &self.config.hotkey {
+            Some(hotkey) => hotkey,
+            None => return,
+        };
+        let (tx, rx) = channel();
+        let ctx = ctx.clone();
+        let on_press = move || {
+            tx.send(()).ok();
+            // update() isn't called while the window is unfocused unless asked for
+            ctx.request_repaint();
+        };
+        match hotkey::listen(hotkey, on_press) {
+            Ok(()) => self.hotkey = Some(rx),
+            Err(e) => println!("Failed to listen for hotkey: {}", e),
+        }
+    }
+
+    /// Shoot once if the hotkey has been pressed since the last frame
+    fn hotkey_fire(&mut self) {
+        let pressed = match &self.hotkey {
+            Some(rx) => rx.try_iter().count() > 0,
+            None => false,
+        };
+        if pressed {
+            if let Err(e) = self.shoot() {
+                println!("Failed to shoot: {}", e);
+            }
+        }
+    }
+
+    /// Capture, detect and fire once. Returns the number of clicks fired
+    fn shoot(&mut self) -> Result<usize, Box<dyn Error>> {
+        let frame = capture(&mut self.source, &self.config)?;
+        self.last_capture = Some(frame.img.clone());
+        let (w, h) = frame.desktop_size;
+        let config = self.config_for(&frame);
+        let (detections, mut clicks, stats) =
+            process_image(frame.img.clone(), self.scan_debug, self.scan_red, &config);
+        print_found(&detections, &clicks, &stats);
+        frame.to_desktop(&mut clicks);
+        if !clicks.is_empty() {
+            let backend = &self.config.click_backend;
+            let reopen = match &self.sink {
+                Some(open) => open.backend != *backend || open.desktop_size != (w, h),
+                None => true,
+            };
+            if reopen {
+                self.sink = Some(OpenSink {
+                    backend: backend.clone(),
+                    desktop_size: (w, h),
+                    sink: backend.open(w, h)?,
+                });
+            }
+            if let Err(e) = self.sink.as_mut().unwrap().sink.click(&clicks) {
+                // Reopen the sink next time, in case it was the sink that broke
+                self.sink = None;
+                return Err(e);
+            }
+        }
+        Ok(clicks.len())
+    }
+
+    /// Shoot if auto fire is on and it is time to, and stop once a scan finds nothing
+    fn auto_fire(&mut self, ctx: &egui::Context) {
+        if !self.auto {
+            return;
+        }
+        let now = Instant::now();
+        if now < self.next_scan {
+            ctx.request_repaint_after(self.next_scan - now);
+            return;
+        }
+        match self.shoot() {
+            Ok(0) => {
+                println!("Auto fire found nothing to shoot, stopping");
+                self.auto = false;
+            }
+            Ok(_) => {
+                self.next_scan = self.config.auto_fire.next_scan(now);
+                ctx.request_repaint_after(self.next_scan - Instant::now());
+            }
+            Err(e) => {
+                println!("Failed to shoot, stopping auto fire: {}", e);
+                self.auto = false;
+            }
+        }
+    }
+
+    fn show_editor(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let Gui {
+            editor,
+            config,
+            config_path,
+            last_capture,
+            source,
+            ..
+        } = self;
+        let editor = editor.as_mut().unwrap();
+        let mut done = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Done").clicked() {
+                    done = true;
+                }
+                if ui.button("Capture").clicked() {
+                    match capture(source, config) {
+                        Ok(frame) => {
+                            editor.set_image(ctx, &frame.img);
+                            *last_capture = Some(frame.img);
+                        }
+                        Err(e) => println!("Failed to capture: {}", e),
+                    }
+                }
+            });
+            if let (false, Some(img)) = (editor.has_image(), &*last_capture) {
+                editor.set_image(ctx, img);
+            }
+            editor.show(ui, config, config_path);
+        });
+        if done {
+            self.editor = None;
+            frame.set_window_size(WINDOW_SIZE);
+        }
+    }
+
+    fn show_calibrator(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let Gui {
+            calibrator,
+            config,
+            config_path,
+            last_capture,
+            source,
+            ..
+        } = self;
+        let calibrator = calibrator.as_mut().unwrap();
+        let mut done = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Done").clicked() {
+                    done = true;
+                }
+                if ui.button("Capture").clicked() {
+                    match capture(source, config) {
+                        Ok(frame) => {
+                            calibrator.set_image(ctx, &frame.img);
+                            *last_capture = Some(frame.img);
+                        }
+                        Err(e) => println!("Failed to capture: {}", e),
+                    }
+                }
+            });
+            if let (false, Some(img)) = (calibrator.has_image(), &*last_capture) {
+                calibrator.set_image(ctx, img);
+            }
+            calibrator.show(ui, config, config_path);
+        });
+        if done {
+            self.calibrator = None;
+            frame.set_window_size(WINDOW_SIZE);
         }
     }
 }
 
 impl eframe::App for Gui {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.track_window(ctx, frame);
+        if self.editor.is_some() {
+            self.show_editor(ctx, frame);
+            return;
+        }
+        if self.calibrator.is_some() {
+            self.show_calibrator(ctx, frame);
+            return;
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.checkbox(&mut self.scan_debug, "Scan Debug");
             ui.checkbox(&mut self.scan_red, "Scan Red");
+            let mut auto_ui_scale = self.config.ui_scale.is_none();
+            ui.checkbox(&mut auto_ui_scale, "Auto UI Scale");
+            match (auto_ui_scale, &mut self.config.ui_scale) {
+                (true, ui_scale) => *ui_scale = None,
+                (false, Some(ui_scale)) => {
+                    ui.add(egui::Slider::new(ui_scale, 0.5..=3.0).text("UI Scale"));
+                }
+                (false, ui_scale) => *ui_scale = Some(1.0),
+            }
+            if ui.button("Edit Zones").clicked() {
+                self.editor = Some(ZoneEditor::default());
+                frame.set_window_size(EDITOR_WINDOW_SIZE);
+            }
+            if ui.button("Calibrate Colour").clicked() {
+                self.calibrator = Some(ColorCalibrator::default());
+                frame.set_window_size(EDITOR_WINDOW_SIZE);
+            }
             let butt = egui::Button::new("Shoot");
             //let butt = butt.fill(egui::Rgba::from_rgb(0.6, 0.2, 0.2));
             let butt = ui.add_sized(egui::vec2(84.3, 42.3), butt);
             if butt.clicked() {
-                let debug = self.scan_debug;
-                let red = self.scan_red;
-                let img = capture_image();
-                let clicks = process_image_into_clicks(img, debug, red);
-                click_arty(&clicks).unwrap();
+                if let Err(e) = self.shoot() {
+                    println!("Failed to shoot: {}", e);
+                }
+            }
+            if ui.checkbox(&mut self.auto, "Auto").changed() && self.auto {
+                self.next_scan = Instant::now();
             }
+            let auto_fire = &mut self.config.auto_fire;
+            ui.add(
+                egui::DragValue::new(&mut auto_fire.interval)
+                    .clamp_range(0.0..=600.0)
+                    .suffix(" s interval"),
+            );
+            ui.add(
+                egui::DragValue::new(&mut auto_fire.cooldown)
+                    .clamp_range(0.0..=600.0)
+                    .suffix(" s cooldown"),
+            );
         });
+        self.hotkey_fire();
+        self.auto_fire(ctx);
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut static_images = false;
-    for arg in std::env::args().skip(1) {
+/// Options for processing images without the GUI
+#[derive(Default)]
+struct Headless {
+    output: Option<OutputFormat>,
+    out_dir: Option<PathBuf>,
+    dump_stages: Option<PathBuf>,
+    click_backend: Option<ClickBackend>,
+    /// Opened on the first set of clicks
+    sink: Option<Box<dyn ClickSink>>,
+}
+
+impl Headless {
+    /// Run the pipeline on a frame, and save or click the results as requested
+    /// name: image path the frame was loaded from, used to name output files
+    fn process(&mut self, name: &str, frame: Frame, config: &Config) -> Result<(), Box<dyn Error>> {
         let now = std::time::Instant::now();
-        let img = ImageReader::open(arg)?.decode()?;
-        let img = img.into_rgb8();
-        let clicks = process_image_into_clicks(img, true, true);
+        let (desktop_w, desktop_h) = frame.desktop_size;
+        if let Some(dir) = &self.dump_stages {
+            let stem = Path::new(name)
+                .file_stem()
+                .ok_or("Image path has no file name")?;
+            for (i, (stage, overlay)) in red_stage_overlays(&frame.img, config).iter().enumerate() {
+                let path = dir.join(format!("{}_{}_{}.png", stem.to_string_lossy(), i, stage));
+                overlay.save(&path)?;
+            }
+        }
+        let (detections, mut clicks, stats) = process_image(frame.img.clone(), true, true, config);
+        print_found(&detections, &clicks, &stats);
         println!(
             "Image processing took {:?} and generated {} clicks",
             now.elapsed(),
             clicks.len()
         );
+        if let Some(format) = self.output {
+            let path =
+                Report::new(name, &detections, &clicks).save(format, self.out_dir.as_deref())?;
+            println!("Wrote {}", path.display());
+        }
+        // Clicks are only sent anywhere when asked for
+        if let Some(backend) = &self.click_backend {
+            if !clicks.is_empty() {
+                if self.sink.is_none() {
+                    self.sink = Some(backend.open(desktop_w, desktop_h)?);
+                }
+                frame.to_desktop(&mut clicks);
+                self.sink.as_mut().unwrap().click(&clicks)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut static_images = false;
+    let mut ui_scale = None;
+    let mut config_path = DEFAULT_CONFIG_PATH.to_owned();
+    let mut headless = Headless::default();
+    let mut frame_source: Option<CaptureBackend> = None;
+    let mut generator_override = None;
+    let mut red_detector_override = None;
+    let mut no_refine = false;
+    let mut images = vec![];
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("evaluate") {
+        args.next();
+        return evaluate_images(args);
+    }
+    if args.peek().map(String::as_str) == Some("synth") {
+        args.next();
+        return synthesize_images(args);
+    }
+    if args.peek().map(String::as_str) == Some("calibrate") {
+        args.next();
+        return calibrate_colors(args);
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ui-scale" => {
+                let scale = args.next().ok_or("--ui-scale requires a value")?;
+                ui_scale = Some(scale.parse::<f64>()?);
+            }
+            "--config" => {
+                config_path = args.next().ok_or("--config requires a path")?;
+            }
+            "--output" => {
+                headless.output = Some(
+                    args.next()
+                        .ok_or("--output requires json or csv")?
+                        .parse()?,
+                );
+            }
+            "--out-dir" => {
+                headless.out_dir = Some(args.next().ok_or("--out-dir requires a path")?.into());
+            }
+            "--click-sink" => {
+                let backend = args.next().ok_or("--click-sink requires a backend")?;
+                headless.click_backend = Some(backend.parse()?);
+            }
+            "--dump-stages" => {
+                headless.dump_stages =
+                    Some(args.next().ok_or("--dump-stages requires a path")?.into());
+            }
+            "--generator" => {
+                let generator = args.next().ok_or("--generator requires a generator")?;
+                generator_override = Some(generator.parse()?);
+            }
+            "--no-refine" => no_refine = true,
+            "--red-detector" => {
+                let red_detector = args.next().ok_or("--red-detector requires a detector")?;
+                red_detector_override = Some(red_detector.parse()?);
+            }
+            "--frame-source" => {
+                let source = args.next().ok_or("--frame-source requires a source")?;
+                frame_source = Some(source.parse()?);
+            }
+            _ => images.push(arg),
+        }
+    }
+    let mut config = Config::load_or_default(&config_path)?;
+    if ui_scale.is_some() {
+        config.ui_scale = ui_scale;
+    }
+    if let Some(generator) = generator_override {
+        config.generator = generator;
+    }
+    if no_refine {
+        config.refine = false;
+    }
+    if let Some(red_detector) = red_detector_override {
+        config.red_detector = red_detector;
+    }
+    for dir in headless.out_dir.iter().chain(&headless.dump_stages) {
+        std::fs::create_dir_all(dir)?;
+    }
+    for arg in images {
+        let img = ImageReader::open(&arg)?.decode()?;
+        headless.process(&arg, Frame::new(img.into_rgb8()), &config)?;
         // If user provides list of images, don't run the normal gui
         static_images = true;
     }
+    if let Some(source) = frame_source {
+        let mut source = source.open()?;
+        let mut i = 0;
+        while let Some(frame) = source.next_frame()? {
+            headless.process(&format!("frame_{:05}.png", i), frame, &config)?;
+            i += 1;
+        }
+        static_images = true;
+    }
     if !static_images {
         let mut options = eframe::NativeOptions::default();
-        options.initial_window_size = Some(egui::vec2(100., 100.));
+        options.initial_window_size = Some(WINDOW_SIZE);
         eframe::run_native(
             "Iron Dome",
             options,
-            Box::new(|_cc| Box::new(Gui::default())),
+            Box::new(|cc| {
+                let mut gui = Gui::new(config, config_path);
+                gui.listen_for_hotkey(&cc.egui_ctx);
+                Box::new(gui)
+            }),
         );
     }
     Ok(())
 }
 
-fn process_image_into_clicks(mut img: RgbImage, scan_debug: bool, scan_red: bool) -> Vec<Coord> {
-    if scan_debug {
-        let mut debug_img = img.clone();
-
-        let debug_spawner_positions = find_spawner_positions(&debug_img);
-        let debug_worm_positions = find_worm_positions(&debug_img);
-
-        let spawner_mask = BoundingBox {
-            left_top: Coord { w: -30, h: -11 },
-            right_bottom: Coord { w: 23, h: 31 },
-        };
-        let spawner_bbs =
-            remap_positions_to_bb(&debug_spawner_positions, &spawner_mask, &mut debug_img);
-
-        let worm_mask = BoundingBox {
-            left_top: Coord { w: -8, h: 1 },
-            right_bottom: Coord { w: 15, h: 22 },
+/// Score both detectors against the annotation file next to each image
+/// The red detector uses the selected colour profile of the config, and both of its algorithms
+/// are scored so they can be compared
+fn evaluate_images(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    let mut config_path = DEFAULT_CONFIG_PATH.to_owned();
+    let mut profile = None;
+    let mut images = vec![];
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} requires a value", name));
+        match arg.as_str() {
+            "--config" => config_path = value("--config")?,
+            "--profile" => profile = Some(value("--profile")?),
+            _ => images.push(arg),
+        }
+    }
+    let mut config = Config::load_or_default(&config_path)?;
+    if let Some(profile) = profile {
+        if profile != VANILLA_COLOR_PROFILE && !config.color_profiles.contains_key(&profile) {
+            return Err(format!("Unknown colour profile {} in {}", profile, config_path).into());
+        }
+        config.color_profile = profile;
+    }
+    let runs = [
+        (Detector::Debug, RedDetector::Scan),
+        (Detector::Red, RedDetector::Scan),
+        (Detector::Red, RedDetector::Components),
+    ];
+    let mut rows = vec![];
+    let mut totals: Vec<Evaluation> = vec![];
+    for image in images {
+        let annotation_path = Annotation::path_for(&image);
+        let annotation = match Annotation::load(&annotation_path) {
+            Ok(annotation) => annotation,
+            Err(e) => {
+                println!(
+                    "Skipping {}, could not load {}: {}",
+                    image,
+                    annotation_path.display(),
+                    e
+                );
+                continue;
+            }
         };
-        let worm_bbs = remap_positions_to_bb(&debug_worm_positions, &worm_mask, &mut debug_img);
-        let mut combined_bbs = spawner_bbs;
-        combined_bbs.extend(&worm_bbs);
-        combined_bbs.sort_by(|s, other| {
-            let res = if s.left_top.h == other.left_top.h {
-                s.left_top.w.cmp(&other.left_top.h)
-            } else {
-                s.left_top.h.cmp(&other.left_top.h)
+        let img = ImageReader::open(&image)?.decode()?.into_rgb8();
+        for (detector, red_detector) in runs {
+            let config = Config {
+                red_detector,
+                ..config.clone()
             };
-            res
-        });
+            let evaluation = evaluate(&img, &annotation, detector, &config);
+            match totals.iter_mut().find(|t| t.label() == evaluation.label()) {
+                Some(total) => total.add(&evaluation),
+                None => totals.push(evaluation.clone()),
+            }
+            rows.push((image.clone(), evaluation));
+        }
+    }
 
-        let mut debug_clicks =
-            gen_clicks_from_bbs_rand(&combined_bbs, ARTY_REMOTE_RADIUS, img.width(), img.height());
-        remove_clicks_in_excluded_areas(&mut debug_clicks);
-        println!(
-            "Debug found {} targets, generated {} clicks",
-            combined_bbs.len(),
-            debug_clicks.len()
-        );
-        if !debug_clicks.is_empty() {
-            return debug_clicks;
+    println!("{:<16}  {}", "image", Evaluation::header());
+    for (image, evaluation) in rows {
+        println!("{:<16}  {}", image, evaluation);
+    }
+    for total in totals {
+        println!("{:<16}  {}", "total", total);
+    }
+    Ok(())
+}
+
+/// Render synthetic screenshots into a directory, each with its annotation file
+fn synthesize_images(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    let mut out_dir = None;
+    let mut count = 1;
+    let mut options = SceneOptions::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} requires a value", name));
+        match arg.as_str() {
+            "--count" => count = value("--count")?.parse()?,
+            "--seed" => options.seed = value("--seed")?.parse()?,
+            "--width" => options.width = value("--width")?.parse()?,
+            "--height" => options.height = value("--height")?.parse()?,
+            "--spawner-width" => options.spawner_width = value("--spawner-width")?.parse()?,
+            "--nests" => options.nests = value("--nests")?.parse()?,
+            "--fog" => options.fog = value("--fog")?.parse()?,
+            "--debug-circles" => options.debug_circles = true,
+            _ => out_dir = Some(PathBuf::from(arg)),
         }
     }
-    if scan_red {
-        let (bbs, spawner_width) = process_red(&mut img);
-        let mut red_clicks = gen_clicks_from_bbs_rand(
-            &bbs,
-            (spawner_width as f64 * 0.43) as u32,
-            img.width(),
-            img.height(),
-        );
-        /*let mut red_clicks = gen_clicks_from_bbs_fixed(
-            &bbs,
-            (spawner_width as f64 * 0.43) as u32,
-            img.width(),
-            img.height(),
-        );*/
-        remove_clicks_in_excluded_areas(&mut red_clicks);
-        //remove_clicks_in_excluded_areas(&mut alt_red_clicks);
-        //dbg!(alt_red_clicks.len());
-        red_clicks.sort_by(|first, second| first.h.cmp(&(second.h)));
+    let out_dir = out_dir.ok_or("synth requires an output directory")?;
+    std::fs::create_dir_all(&out_dir)?;
+    let first_seed = options.seed;
+    for i in 0..count {
+        options.seed = first_seed + i;
+        let scene = synthetic::generate(&options);
+        let path = out_dir.join(format!("synth_{:05}.png", options.seed));
+        scene.img.save(&path)?;
+        scene
+            .annotation(SolverOptions::default())
+            .save(Annotation::path_for(&path))?;
         println!(
-            "Red found {} targets, generated {} clicks",
-            bbs.len(),
-            red_clicks.len()
+            "Wrote {} with {} targets",
+            path.display(),
+            scene.targets.len()
         );
-        return red_clicks;
     }
-    vec![]
-}
-
-fn remove_clicks_in_excluded_areas(clicks: &mut Vec<Coord>) {
-    clicks
-        .retain(|click| !(click.h < (493 * SCREEN_H / 1080) && click.w > (1664 * SCREEN_W / 1920)));
-    clicks.retain(|click| {
-        !(click.h > (985 * SCREEN_H / 1080)
-            && click.w > (703 * SCREEN_W / 1920)
-            && click.w < (1433 * SCREEN_W / 1920))
-    });
+    Ok(())
 }
 
-#[allow(dead_code)]
-fn click_arty(clicks: &[Coord]) -> Result<(), Box<(dyn Error)>> {
-    let mut cmd = String::new();
-    for click in clicks {
-        cmd.push_str(&format!("mousemove {} {} click 1 ", click.w, click.h));
+/// Fit the enemy colour classifier to pixels of an image and save it to the selected colour
+/// profile of the config
+fn calibrate_colors(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    let mut config_path = DEFAULT_CONFIG_PATH.to_owned();
+    let mut image = None;
+    let mut profile = None;
+    let mut preview = None;
+    let mut dry_run = false;
+    let mut enemy = vec![];
+    let mut background = vec![];
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} requires a value", name));
+        match arg.as_str() {
+            "--config" => config_path = value("--config")?,
+            "--enemy" => enemy.push(parse_pixel(&value("--enemy")?)?),
+            "--background" => background.push(parse_pixel(&value("--background")?)?),
+            "--profile" => profile = Some(value("--profile")?),
+            "--preview" => preview = Some(PathBuf::from(value("--preview")?)),
+            "--dry-run" => dry_run = true,
+            _ => image = Some(arg),
+        }
+    }
+    let image = image.ok_or("calibrate requires an image")?;
+    let img = ImageReader::open(&image)?.decode()?.into_rgb8();
+    let pixels = |picked: &[Coord]| -> Result<Vec<_>, String> {
+        picked
+            .iter()
+            .map(|p| {
+                if p.w < 0 || p.h < 0 || p.w >= img.width() as i64 || p.h >= img.height() as i64 {
+                    return Err(format!("{},{} is outside {}", p.w, p.h, image));
+                }
+                Ok(*img.get_pixel(p.w as u32, p.h as u32))
+            })
+            .collect()
+    };
+    let classifier = fit(&pixels(&enemy)?, &pixels(&background)?)?;
+    let enemy_pixels = img
+        .pixels()
+        .filter(|px| classifier.looks_like_enemy(px))
+        .count();
+    println!(
+        "Fitted {:?}, which sees {} of the {} pixels as enemies",
+        classifier,
+        enemy_pixels,
+        img.width() * img.height()
+    );
+    if let Some(path) = preview {
+        classifier_preview(&img, &classifier).save(&path)?;
+        println!("Wrote {}", path.display());
+    }
+    if !dry_run {
+        let mut config = Config::load_or_default(&config_path)?;
+        if let Some(profile) = profile {
+            config.color_profile = profile;
+        }
+        config.set_classifier(classifier);
+        config.save(&config_path)?;
+        println!(
+            "Saved to profile {} in {}",
+            config.color_profile, config_path
+        );
     }
-
-    let _o = std::process::Command::new("xdotool")
-        .args(cmd.split_whitespace())
-        .output()?;
     Ok(())
 }
 
-fn capture_image() -> RgbImage {
-    let mut c = Capturer::new(0).unwrap();
-
-    let bytes = c.capture_frame().unwrap();
-    let bytes = bytes
-        .into_iter()
-        .map(|bgr| [bgr.r, bgr.g, bgr.b])
-        .flatten()
-        .collect::<Vec<u8>>();
-    let geometry = c.geometry();
+/// Parses <w>,<h>
+fn parse_pixel(s: &str) -> Result<Coord, String> {
+    let bad = || format!("Invalid pixel {}, expected <w>,<h>", s);
+    let (w, h) = s.split_once(',').ok_or_else(bad)?;
+    Ok(Coord {
+        w: w.trim().parse().map_err(|_| bad())?,
+        h: h.trim().parse().map_err(|_| bad())?,
+    })
+}
 
-    RgbImage::from_raw(geometry.0, geometry.1, bytes).unwrap()
+/// Print what `process_image` found and how each planning round went
+fn print_found(detections: &Detections, clicks: &[Coord], stats: &[PlanStats]) {
+    if detections.detector != Detector::None {
+        println!(
+            "{:?} found {} targets, generated {} clicks",
+            detections.detector,
+            detections.bbs.len(),
+            clicks.len()
+        );
+    }
+    for round in stats {
+        println!("{}", round);
+    }
 }
 
-#[allow(dead_code)]
-fn draw_bbs(bbs: &[BoundingBox]) {
-    let mut i = RgbImage::new(SCREEN_W as u32, SCREEN_H as u32);
-    for bb in bbs {
-        if bb.left_top.w < 0 || bb.left_top.h < 0 {
-            continue;
-        }
-        if bb.right_bottom.w >= SCREEN_W || bb.right_bottom.h >= SCREEN_H {
-            continue;
-        }
-        if bb.left_top.w >= SCREEN_W || bb.left_top.h >= SCREEN_H {
-            continue;
-        }
-        let shift = bb.area() as u8;
-        for w in bb.left_top.w..bb.right_bottom.w {
-            if w >= SCREEN_W {
-                continue;
-            }
-            i.put_pixel(w as u32, bb.left_top.h as u32, Rgb([shift, shift, 0xff]));
-            i.put_pixel(
-                w as u32,
-                bb.right_bottom.h as u32,
-                Rgb([shift, shift, 0xff]),
-            );
+/// Take a screenshot with the frame source from the config
+/// source: the source opened by an earlier screenshot, kept open between screenshots. It is
+/// reopened when the config picks another backend, and after an error.
+fn capture(
+    source: &mut Option<(CaptureBackend, Box<dyn FrameSource>)>,
+    config: &Config,
+) -> Result<Frame, Box<dyn Error>> {
+    let backend = &config.capture_backend;
+    if !matches!(source, Some((b, _)) if b == backend) {
+        *source = Some((backend.clone(), backend.open()?));
+    }
+    match source.as_mut().unwrap().1.next_frame() {
+        Ok(Some(frame)) => Ok(frame),
+        Ok(None) => {
+            *source = None;
+            Err("Frame source has no more frames".into())
         }
-        for h in bb.left_top.h..bb.right_bottom.h {
-            if h >= SCREEN_H {
-                continue;
-            }
-            i.put_pixel(bb.left_top.w as u32, h as u32, Rgb([shift, shift, 0xff]));
-            i.put_pixel(
-                bb.right_bottom.w as u32,
-                h as u32,
-                Rgb([shift, shift, 0xff]),
-            );
+        Err(e) => {
+            *source = None;
+            Err(e)
         }
     }
-    i.save("bbs.png").unwrap();
 }
@@ -0,0 +1,148 @@
+//! Module to save the detections and clicks for an image in a machine readable format
+
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::screen::{BoundingBox, Coord};
+use crate::{Detections, Detector, TargetClass};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format {}, expected json or csv", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Target {
+    pub class: TargetClass,
+    pub bb: BoundingBox,
+}
+
+/// Everything found and planned for a single image
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub image: String,
+    pub detector: Detector,
+    /// Width in pixels of a spawner, as deduced by the red detector
+    pub spawner_width: Option<i64>,
+    pub remote_radius: u32,
+    pub targets: Vec<Target>,
+    pub clicks: Vec<Coord>,
+}
+
+impl Report {
+    pub fn new(image: impl Into<String>, detections: &Detections, clicks: &[Coord]) -> Self {
+        Report {
+            image: image.into(),
+            detector: detections.detector,
+            spawner_width: detections.spawner_width,
+            remote_radius: detections.remote_radius,
+            targets: detections
+                .bbs
+                .iter()
+                .zip(&detections.classes)
+                .map(|(bb, class)| Target {
+                    class: *class,
+                    bb: *bb,
+                })
+                .collect(),
+            clicks: clicks.to_vec(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// One row per target and per click. Clicks leave the class and right_bottom columns empty
+    pub fn to_csv(&self) -> String {
+        let image = csv_field(&self.image);
+        let detector = format!("{:?}", self.detector).to_lowercase();
+        let spawner_width = self
+            .spawner_width
+            .map(|w| w.to_string())
+            .unwrap_or_default();
+        let mut csv = String::from(
+            "image,detector,spawner_width,remote_radius,kind,class,w,h,right_bottom_w,right_bottom_h\n",
+        );
+        for target in &self.targets {
+            let class = format!("{:?}", target.class).to_lowercase();
+            writeln!(
+                csv,
+                "{},{},{},{},target,{},{},{},{},{}",
+                image,
+                detector,
+                spawner_width,
+                self.remote_radius,
+                class,
+                target.bb.left_top.w,
+                target.bb.left_top.h,
+                target.bb.right_bottom.w,
+                target.bb.right_bottom.h
+            )
+            .unwrap();
+        }
+        for click in &self.clicks {
+            writeln!(
+                csv,
+                "{},{},{},{},click,,{},{},,",
+                image, detector, spawner_width, self.remote_radius, click.w, click.h
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// Write the report for image into out_dir, or next to the image if out_dir is None
+    pub fn save(
+        &self,
+        format: OutputFormat,
+        out_dir: Option<&Path>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let image = Path::new(&self.image);
+        let path = match out_dir {
+            Some(dir) => dir.join(image.file_name().ok_or("Image path has no file name")?),
+            None => image.to_path_buf(),
+        }
+        .with_extension(format.extension());
+        let text = match format {
+            OutputFormat::Json => self.to_json()?,
+            OutputFormat::Csv => self.to_csv(),
+        };
+        std::fs::write(&path, text)?;
+        Ok(path)
+    }
+}
+
+/// field as a CSV field, quoted with its quotes doubled when it holds a comma, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,10 +1,23 @@
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+//! Module for pixel coordinates on the screen
+//!
+//! Bounds are inclusive: a `BoundingBox` covers every pixel from `left_top` to `right_bottom`,
+//! including both corners, and is only valid when `left_top` is not right of or below
+//! `right_bottom`. Its `w` and `h` are the distances between its first and last pixels, one less
+//! than the number of pixels across, so that `BoundingBox::new(left_top, bb.w(), bb.h())` gives
+//! bb back. Distances are measured between pixels, so a circle of radius r around a pixel covers
+//! the pixels at most r away.
+
+use serde::{Deserialize, Serialize};
+
+/// A rectangle of pixels, see the module docs for its bounds
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct BoundingBox {
     pub left_top: Coord,
     pub right_bottom: Coord,
 }
 
 impl BoundingBox {
+    /// The box from left_top to the pixel w right and h below it
     pub fn new(left_top: Coord, w: i64, h: i64) -> Self {
         Self {
             left_top,
@@ -15,6 +28,7 @@ impl BoundingBox {
         }
     }
 
+    /// Every pixel in the box, row by row
     pub fn enumerate(&self) -> impl Iterator<Item = (i64, i64)> {
         let ltw = self.left_top.w;
         let rbw = self.right_bottom.w;
@@ -30,6 +44,14 @@ impl BoundingBox {
             && pos.h <= self.right_bottom.h
     }
 
+    pub fn collides_with_bb(&self, other: &BoundingBox) -> bool {
+        self.left_top.w <= other.right_bottom.w
+            && self.right_bottom.w >= other.left_top.w
+            && self.left_top.h <= other.right_bottom.h
+            && self.right_bottom.h >= other.left_top.h
+    }
+
+    /// Whether any pixel of the box is at most radius from pos
     pub fn collides_with_circle(&self, pos: Coord, radius: u32) -> bool {
         if !self.collides_fast_calc(pos, radius as i64) {
             return false;
@@ -52,14 +74,11 @@ impl BoundingBox {
         }
         let distx = pos.w - testx;
         let disty = pos.h - testy;
-        let distsq = (distx.pow(2) + disty.pow(2)) as f64;
-        let dist = f64::sqrt(distsq);
-        if dist as u32 <= radius {
-            return true;
-        }
-        false
+        distx.pow(2) + disty.pow(2) <= (radius as i64).pow(2)
     }
 
+    /// Whether pos is within radius of the box on both axes. Never false when
+    /// `collides_with_circle` is true, so it can be used to skip boxes that are too far away.
     pub fn collides_fast_calc(&self, pos: Coord, radius: i64) -> bool {
         pos.w >= self.left_top.w - radius
             && pos.w <= self.right_bottom.w + radius
@@ -67,10 +86,16 @@ impl BoundingBox {
             && pos.h <= self.right_bottom.h + radius
     }
 
+    /// w * h, the area between the centres of the corner pixels
     pub fn area(&self) -> i64 {
         self.w() * self.h()
     }
 
+    /// Number of pixels in the box, (w + 1) * (h + 1)
+    pub fn pixel_count(&self) -> i64 {
+        (self.w() + 1) * (self.h() + 1)
+    }
+
     pub fn w(&self) -> i64 {
         self.right_bottom.w - self.left_top.w
     }
@@ -79,13 +104,131 @@ impl BoundingBox {
         self.right_bottom.h - self.left_top.h
     }
 
+    /// w / h
     pub fn ratio(&self) -> f64 {
         self.w() as f64 / self.h() as f64
     }
 }
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+/// A pixel, w pixels from the left and h pixels from the top of the screen
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct Coord {
     pub w: i64,
     pub h: i64,
 }
+
+/// Uniform grid over a set of bounding boxes, to find the boxes near a point without checking
+/// every box. Each box is stored in every cell it overlaps.
+#[derive(Debug, Clone)]
+pub struct BoxGrid {
+    bbs: Vec<BoundingBox>,
+    cell_size: i64,
+    origin: Coord,
+    cols: i64,
+    rows: i64,
+    /// Indexes into bbs of the boxes overlapping each cell, row by row
+    cells: Vec<Vec<usize>>,
+}
+
+impl BoxGrid {
+    /// Index bbs in square cells of cell_size pixels. Queries are fastest when a cell is about
+    /// the size of the area they cover. Cells are never smaller than the median bb, as a bb is
+    /// stored in every cell it overlaps, and a tiny cell_size (eg from a remote radius of 0)
+    /// would make a cell for every pixel.
+    pub fn new(bbs: &[BoundingBox], cell_size: i64) -> Self {
+        let mut sizes = bbs
+            .iter()
+            .map(|bb| bb.w().max(bb.h()) + 1)
+            .collect::<Vec<_>>();
+        sizes.sort_unstable();
+        let median_size = sizes.get(sizes.len() / 2).copied().unwrap_or(1);
+        let cell_size = cell_size.max(median_size).max(1);
+        let origin = Coord {
+            w: bbs.iter().map(|bb| bb.left_top.w).min().unwrap_or(0),
+            h: bbs.iter().map(|bb| bb.left_top.h).min().unwrap_or(0),
+        };
+        let right = bbs.iter().map(|bb| bb.right_bottom.w).max().unwrap_or(0);
+        let bottom = bbs.iter().map(|bb| bb.right_bottom.h).max().unwrap_or(0);
+        let mut grid = BoxGrid {
+            bbs: bbs.to_vec(),
+            cell_size,
+            origin,
+            cols: (right - origin.w).div_euclid(cell_size) + 1,
+            rows: (bottom - origin.h).div_euclid(cell_size) + 1,
+            cells: vec![],
+        };
+        grid.cells = vec![vec![]; (grid.cols * grid.rows) as usize];
+        for (i, bb) in bbs.iter().enumerate() {
+            let (left, top) = grid.cell_of(bb.left_top);
+            let (right, bottom) = grid.cell_of(bb.right_bottom);
+            for row in top..=bottom {
+                for col in left..=right {
+                    grid.cells[(row * grid.cols + col) as usize].push(i);
+                }
+            }
+        }
+        grid
+    }
+
+    /// The indexed boxes, in the order they were given
+    pub fn bbs(&self) -> &[BoundingBox] {
+        &self.bbs
+    }
+
+    /// Column and row of the cell containing pos, clamped to the grid
+    fn cell_of(&self, pos: Coord) -> (i64, i64) {
+        (
+            (pos.w - self.origin.w)
+                .div_euclid(self.cell_size)
+                .clamp(0, self.cols - 1),
+            (pos.h - self.origin.h)
+                .div_euclid(self.cell_size)
+                .clamp(0, self.rows - 1),
+        )
+    }
+
+    /// Call f once with the index of every box that `collides_with_circle(pos, radius)`
+    pub fn query_circle(&self, pos: Coord, radius: u32, mut f: impl FnMut(usize)) {
+        let r = radius as i64;
+        let area = BoundingBox {
+            left_top: Coord {
+                w: pos.w - r,
+                h: pos.h - r,
+            },
+            right_bottom: Coord {
+                w: pos.w + r,
+                h: pos.h + r,
+            },
+        };
+        if !area.collides_with_bb(&self.bounds()) {
+            return;
+        }
+        let (left, top) = self.cell_of(area.left_top);
+        let (right, bottom) = self.cell_of(area.right_bottom);
+        for row in top..=bottom {
+            for col in left..=right {
+                for &i in &self.cells[(row * self.cols + col) as usize] {
+                    let bb = &self.bbs[i];
+                    // A box overlapping several cells is only reported from the cell holding the
+                    // left top corner of its overlap with the area
+                    let corner = Coord {
+                        w: bb.left_top.w.max(area.left_top.w),
+                        h: bb.left_top.h.max(area.left_top.h),
+                    };
+                    if self.cell_of(corner) == (col, row) && bb.collides_with_circle(pos, radius) {
+                        f(i);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The area covered by the grid's cells
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(
+            self.origin,
+            self.cols * self.cell_size - 1,
+            self.rows * self.cell_size - 1,
+        )
+    }
+}
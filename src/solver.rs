@@ -0,0 +1,622 @@
+//! Module to plan the fewest clicks that hit every bounding box, solved as a set cover problem.
+//!
+//! A click hits a bounding box if it is within the remote radius of it, as
+//! `BoundingBox::collides_with_circle` measures it, so the pixels that hit a box are the box grown
+//! by the radius with rounded corners. Any click can be slid left and up, one pixel at a time,
+//! without leaving any box it hits, until it is the leftmost pixel in its row of one box's area
+//! and the topmost pixel in its column of another's (or of the same box's, or the image's). Those
+//! pixels are the candidate clicks: every set of boxes that a single click can hit is hit by one
+//! of them. Choosing the fewest candidates that hit every box is solved one group of overlapping
+//! boxes at a time: a Lagrangian relaxation gives a lower bound and a good starting cover, then
+//! branch and bound searches for a smaller cover until the time budget runs out. A search that
+//! finishes in time has found the fewest clicks of any in the image.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::screen::BoundingBox;
+use crate::screen::Coord;
+
+/// Tolerance for a Lagrangian bound to count as reaching a whole number
+const EPSILON: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SolverOptions {
+    /// Time to find the candidate clicks, bound and search for the fewest clicks before settling
+    /// for the best found. Groups of bbs not solved by then still get a greedy solution, so
+    /// planning can run a little over.
+    pub time_budget_ms: u64,
+}
+
+impl Default for SolverOptions {
+    fn default() -> Self {
+        SolverOptions {
+            time_budget_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub clicks: Vec<Coord>,
+    /// No set of clicks in the image can hit every bb with fewer clicks than this
+    pub lower_bound: usize,
+    /// Number of candidate clicks the solution was chosen from, after removing dominated ones
+    pub candidates: usize,
+}
+
+impl Solution {
+    /// The solution is proven to use the fewest clicks possible
+    pub fn optimal(&self) -> bool {
+        self.clicks.len() <= self.lower_bound
+    }
+
+    /// How far the solution may be from optimal, as a fraction of its clicks
+    pub fn gap(&self) -> f64 {
+        if self.clicks.is_empty() {
+            return 0.;
+        }
+        (self.clicks.len() - self.lower_bound.min(self.clicks.len())) as f64
+            / self.clicks.len() as f64
+    }
+}
+
+/// Find the fewest clicks that hit every bb, or the best found within the time budget.
+/// bbs that no click in the image can reach are left out.
+/// bbs: a slice of BoundingBox objects to target
+/// remote_radius: estimated size of the artillery remote target area (in pixels)
+/// w: width of the image in pixels (to keep generated clicks in bounds)
+/// h: height of the image in pixels (to keep generated clicks in bounds)
+pub fn solve(
+    bbs: &[BoundingBox],
+    remote_radius: u32,
+    w: u32,
+    h: u32,
+    options: SolverOptions,
+) -> Solution {
+    let deadline = Instant::now() + Duration::from_millis(options.time_budget_ms);
+    let area = HitArea::new(remote_radius, w, h);
+    let candidates = candidate_clicks(bbs, area);
+    let mut instances = split_instances(bbs.len(), candidates, deadline);
+    // Solve small groups first, so the time budget is spent where it is needed
+    instances.sort_by_key(|instance| instance.elements);
+
+    let mut searches = instances
+        .iter()
+        .map(|instance| Search::new(instance, deadline))
+        .collect::<Vec<_>>();
+    // Every group gets its starting bounds before any is searched, so a hard small group can't
+    // use up the time of the big groups after it
+    for search in &mut searches {
+        search.relax();
+    }
+
+    let mut solution = Solution {
+        clicks: vec![],
+        lower_bound: 0,
+        candidates: 0,
+    };
+    for (instance, search) in instances.iter().zip(searches) {
+        let (chosen, lower_bound) = search.run();
+        solution
+            .clicks
+            .extend(chosen.into_iter().map(|c| instance.points[c]));
+        solution.lower_bound += lower_bound;
+        solution.candidates += instance.sets.len();
+    }
+    solution
+}
+
+/// The pixels of the image where a click hits a bb, matching `BoundingBox::collides_with_circle`:
+/// within the radius of the bb, a rectangle with rounded corners
+#[derive(Debug, Clone, Copy)]
+struct HitArea {
+    radius: u32,
+    /// Last pixel of the image on each axis
+    last_w: i64,
+    last_h: i64,
+}
+
+impl HitArea {
+    fn new(remote_radius: u32, w: u32, h: u32) -> Self {
+        HitArea {
+            radius: remote_radius,
+            last_w: w as i64 - 1,
+            last_h: h as i64 - 1,
+        }
+    }
+
+    /// How far a click can be past a bb on one axis when it is offset past the bb on the other
+    fn reach(&self, offset: i64) -> Option<i64> {
+        let radius = self.radius as i64;
+        if offset > radius {
+            return None;
+        }
+        let room = radius * radius - offset * offset;
+        let mut reach = (room as f64).sqrt() as i64;
+        while reach * reach > room {
+            reach -= 1;
+        }
+        while (reach + 1) * (reach + 1) <= room {
+            reach += 1;
+        }
+        Some(reach)
+    }
+
+    /// The leftmost pixel in row h that hits bb
+    fn leftmost(&self, bb: &BoundingBox, h: i64) -> Option<i64> {
+        let reach = self.reach(distance_outside(bb.left_top.h, bb.right_bottom.h, h))?;
+        let left = (bb.left_top.w - reach).max(0);
+        (left <= (bb.right_bottom.w + reach).min(self.last_w)).then_some(left)
+    }
+
+    /// The topmost pixel in column w that hits bb
+    fn topmost(&self, bb: &BoundingBox, w: i64) -> Option<i64> {
+        let reach = self.reach(distance_outside(bb.left_top.w, bb.right_bottom.w, w))?;
+        let top = (bb.left_top.h - reach).max(0);
+        (top <= (bb.right_bottom.h + reach).min(self.last_h)).then_some(top)
+    }
+}
+
+/// How far x is past the range from lo to hi, 0 if it is in the range
+fn distance_outside(lo: i64, hi: i64, x: i64) -> i64 {
+    (lo - x).max(x - hi).max(0)
+}
+
+/// A click, and the indexes of the bbs it hits in ascending order
+type Candidate = (Coord, Vec<usize>);
+
+/// Every distinct set of bbs that some click can hit, and a click that hits it
+fn candidate_clicks(bbs: &[BoundingBox], area: HitArea) -> Vec<Candidate> {
+    // Anything a click that hits bb i can hit is within twice the radius of bb i
+    let reach = 2. * area.radius as f64;
+    let near = (0..bbs.len())
+        .map(|i| {
+            (0..bbs.len())
+                .filter(|&j| j != i && bb_distance(&bbs[i], &bbs[j]) <= reach)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut sets: HashMap<Vec<usize>, Coord> = HashMap::new();
+    for (i, bb) in bbs.iter().enumerate() {
+        let radius = area.radius as i64;
+        let rows = (bb.left_top.h - radius).max(0)..=(bb.right_bottom.h + radius).min(area.last_h);
+        for h in rows {
+            let w = match area.leftmost(bb, h) {
+                Some(w) => w,
+                None => continue,
+            };
+            let click = Coord { w, h };
+            let mut hits = std::iter::once(i)
+                .chain(near[i].iter().copied())
+                .filter(|&j| bbs[j].collides_with_circle(click, area.radius))
+                .collect::<Vec<_>>();
+            // A click that can still slide up hits the same bbs, or more, from higher up
+            if !hits.iter().any(|&j| area.topmost(&bbs[j], w) == Some(h)) {
+                continue;
+            }
+            hits.sort_unstable();
+            sets.entry(hits).or_insert(click);
+        }
+    }
+
+    let mut candidates = sets
+        .into_iter()
+        .map(|(hits, click)| (click, hits))
+        .collect::<Vec<_>>();
+    // Keep the same order every run
+    candidates.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+    candidates
+}
+
+/// Shortest distance between two bbs, 0 if they overlap
+fn bb_distance(a: &BoundingBox, b: &BoundingBox) -> f64 {
+    let dw = (b.left_top.w - a.right_bottom.w)
+        .max(a.left_top.w - b.right_bottom.w)
+        .max(0);
+    let dh = (b.left_top.h - a.right_bottom.h)
+        .max(a.left_top.h - b.right_bottom.h)
+        .max(0);
+    ((dw * dw + dh * dh) as f64).sqrt()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        BitSet(vec![0; len.div_ceil(64)])
+    }
+
+    fn full(len: usize) -> Self {
+        let mut set = BitSet::new(len);
+        for i in 0..len {
+            set.insert(i);
+        }
+        set
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a |= b;
+        }
+    }
+
+    fn is_superset(&self, other: &BitSet) -> bool {
+        self.0.iter().zip(&other.0).all(|(a, b)| a & b == *b)
+    }
+
+    /// Number of elements in self that are not in covered
+    fn count_new(&self, covered: &BitSet) -> u32 {
+        self.0
+            .iter()
+            .zip(&covered.0)
+            .map(|(a, b)| (a & !b).count_ones())
+            .sum()
+    }
+}
+
+/// A group of bbs that no candidate click shares with any other group
+struct Instance {
+    elements: usize,
+    sets: Vec<BitSet>,
+    /// Where each candidate is
+    points: Vec<Coord>,
+    /// The elements that each candidate hits
+    elements_of: Vec<Vec<usize>>,
+    /// The candidates that hit each element
+    hit_by: Vec<Vec<usize>>,
+    /// Elements ordered by fewest candidates first, the order used for lower bounds
+    by_choice: Vec<usize>,
+}
+
+/// Split the candidates into groups of bbs connected by shared candidates, and drop candidates
+/// that hit a subset of another candidate's bbs. Dominated candidates are kept once the deadline
+/// has passed, which makes the search slower but not wrong.
+fn split_instances(
+    num_bbs: usize,
+    mut candidates: Vec<Candidate>,
+    deadline: Instant,
+) -> Vec<Instance> {
+    let mut parent = (0..num_bbs).collect::<Vec<_>>();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        let mut i = i;
+        while parent[i] != root {
+            let next = parent[i];
+            parent[i] = root;
+            i = next;
+        }
+        root
+    }
+    for (_, hits) in &candidates {
+        for &i in &hits[1..] {
+            let (a, b) = (find(&mut parent, hits[0]), find(&mut parent, i));
+            parent[a] = b;
+        }
+    }
+
+    // Bigger sets first, so a candidate can only be dominated by one before it
+    candidates.sort_by_key(|(_, hits)| Reverse(hits.len()));
+    // Members and candidates of each group, by the group's root bb. bbs that no candidate hits
+    // are out of reach of the image, and left out.
+    let mut reachable = vec![false; num_bbs];
+    for (_, hits) in &candidates {
+        for &i in hits {
+            reachable[i] = true;
+        }
+    }
+    let mut groups: BTreeMap<usize, (Vec<usize>, Vec<Candidate>)> = BTreeMap::new();
+    for i in (0..num_bbs).filter(|&i| reachable[i]) {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().0.push(i);
+    }
+    for candidate in candidates {
+        let root = find(&mut parent, candidate.1[0]);
+        groups.get_mut(&root).unwrap().1.push(candidate);
+    }
+
+    let mut instances = vec![];
+    for (_, (members, candidates)) in groups {
+        let local = members
+            .iter()
+            .enumerate()
+            .map(|(local, &global)| (global, local))
+            .collect::<HashMap<_, _>>();
+        let mut instance = Instance {
+            elements: members.len(),
+            sets: vec![],
+            points: vec![],
+            elements_of: vec![],
+            hit_by: vec![vec![]; members.len()],
+            by_choice: vec![],
+        };
+        for (point, hits) in candidates {
+            let elements = hits.iter().map(|i| local[i]).collect::<Vec<_>>();
+            let mut set = BitSet::new(members.len());
+            for &e in &elements {
+                set.insert(e);
+            }
+            // Any candidate hitting a superset also hits the first element
+            let dominated = Instant::now() <= deadline
+                && instance.hit_by[elements[0]]
+                    .iter()
+                    .any(|&other| instance.sets[other].is_superset(&set));
+            if dominated {
+                continue;
+            }
+            for &e in &elements {
+                instance.hit_by[e].push(instance.sets.len());
+            }
+            instance.sets.push(set);
+            instance.elements_of.push(elements);
+            instance.points.push(point);
+        }
+        let mut by_choice = (0..members.len()).collect::<Vec<_>>();
+        by_choice.sort_by_key(|&e| instance.hit_by[e].len());
+        instance.by_choice = by_choice;
+        instances.push(instance);
+    }
+    instances
+}
+
+/// Most subgradient steps of the Lagrangian relaxation
+const LAGRANGIAN_ITERATIONS: usize = 3000;
+/// Steps without a better bound before the step size is halved
+const LAGRANGIAN_PATIENCE: usize = 30;
+
+/// Depth first branch and bound over which candidate hits the element with the fewest choices
+struct Search<'a> {
+    instance: &'a Instance,
+    deadline: Instant,
+    all: BitSet,
+    best: Vec<usize>,
+    /// Lower bound on the size of any cover
+    root_bound: usize,
+    timed_out: bool,
+}
+
+impl<'a> Search<'a> {
+    /// Start from the greedy cover and the simplest lower bound
+    fn new(instance: &'a Instance, deadline: Instant) -> Self {
+        let mut search = Search {
+            instance,
+            deadline,
+            all: BitSet::full(instance.elements),
+            best: vec![],
+            root_bound: 0,
+            timed_out: false,
+        };
+        search.best = search.greedy();
+        search.root_bound = search.lower_bound(&BitSet::new(instance.elements));
+        search
+    }
+
+    /// Tighten the starting bounds with the Lagrangian relaxation, until the deadline
+    fn relax(&mut self) {
+        let (lagrangian_bound, lagrangian_best) = self.lagrangian();
+        self.root_bound = self.root_bound.max(lagrangian_bound);
+        if lagrangian_best.len() < self.best.len() {
+            self.best = lagrangian_best;
+        }
+    }
+
+    /// Search for a smaller cover until the deadline.
+    /// Returns the chosen candidates and a lower bound on how many are needed
+    fn run(mut self) -> (Vec<usize>, usize) {
+        if self.best.len() > self.root_bound {
+            self.search(&BitSet::new(self.instance.elements), &mut vec![]);
+        }
+        let lower_bound = if self.timed_out {
+            self.root_bound
+        } else {
+            self.best.len()
+        };
+        (self.best, lower_bound)
+    }
+
+    /// Repeatedly choose the candidate that hits the most bbs not hit yet
+    fn greedy(&self) -> Vec<usize> {
+        let instance = self.instance;
+        let mut covered = BitSet::new(instance.elements);
+        let mut chosen = vec![];
+        // How many new bbs each candidate hits only goes down as more are hit, so a candidate
+        // that is still on top of the heap after its count is brought up to date is the best
+        let mut heap = (0..instance.sets.len())
+            .map(|c| (instance.elements_of[c].len() as u32, Reverse(c)))
+            .collect::<BinaryHeap<_>>();
+        while covered != self.all {
+            let (count, Reverse(c)) = heap.pop().unwrap();
+            let new = instance.sets[c].count_new(&covered);
+            if new < count {
+                heap.push((new, Reverse(c)));
+                continue;
+            }
+            covered.union_with(&instance.sets[c]);
+            chosen.push(c);
+        }
+        chosen
+    }
+
+    /// Lagrangian relaxation of the set cover, solved by subgradient optimisation.
+    /// Each element has a price, and any set of prices gives a lower bound: the sum of the
+    /// prices, less what the candidates that cost less than the prices they collect save.
+    /// The candidates chosen by each relaxation are also repaired into a cover.
+    /// Returns the best lower bound and the smallest cover found
+    fn lagrangian(&self) -> (usize, Vec<usize>) {
+        let instance = self.instance;
+        let mut prices = (0..instance.elements)
+            .map(|e| {
+                instance.hit_by[e]
+                    .iter()
+                    .map(|&c| 1. / instance.elements_of[c].len() as f64)
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .collect::<Vec<_>>();
+        let mut best_bound = 0.;
+        let mut best = self.best.clone();
+        let mut step = 2.;
+        let mut since_improved = 0;
+        for iteration in 0..LAGRANGIAN_ITERATIONS {
+            if Instant::now() > self.deadline {
+                break;
+            }
+            let costs = instance
+                .elements_of
+                .iter()
+                .map(|elements| 1. - elements.iter().map(|&e| prices[e]).sum::<f64>())
+                .collect::<Vec<_>>();
+            let bound = prices.iter().sum::<f64>() + costs.iter().map(|c| c.min(0.)).sum::<f64>();
+            if bound > best_bound + EPSILON {
+                best_bound = bound;
+                since_improved = 0;
+            } else {
+                since_improved += 1;
+                if since_improved >= LAGRANGIAN_PATIENCE {
+                    step /= 2.;
+                    since_improved = 0;
+                }
+            }
+            if (best_bound - EPSILON).ceil() as usize >= best.len() || step < 0.005 {
+                break;
+            }
+
+            let chosen = (0..costs.len())
+                .filter(|&c| costs[c] < 0.)
+                .collect::<Vec<_>>();
+            if iteration % 10 == 0 {
+                let cover = self.repair(&chosen, &costs);
+                if cover.len() < best.len() {
+                    best = cover;
+                }
+            }
+            // Raise the price of elements no chosen candidate hits, lower it for those hit twice
+            let mut gradient = vec![1.; instance.elements];
+            for &c in &chosen {
+                for &e in &instance.elements_of[c] {
+                    gradient[e] -= 1.;
+                }
+            }
+            let norm = gradient.iter().map(|g| g * g).sum::<f64>();
+            if norm == 0. {
+                break;
+            }
+            let t = step * (best.len() as f64 - bound) / norm;
+            for (price, g) in prices.iter_mut().zip(&gradient) {
+                *price = (*price + t * g).max(0.);
+            }
+        }
+        ((best_bound - EPSILON).ceil().max(0.) as usize, best)
+    }
+
+    /// Add candidates until every element is hit, then drop the costliest candidates that
+    /// only hit elements other candidates also hit
+    fn repair(&self, chosen: &[usize], costs: &[f64]) -> Vec<usize> {
+        let instance = self.instance;
+        let mut chosen = chosen.to_vec();
+        let mut hits = vec![0; instance.elements];
+        for &c in &chosen {
+            for &e in &instance.elements_of[c] {
+                hits[e] += 1;
+            }
+        }
+        for &e in &instance.by_choice {
+            if hits[e] > 0 {
+                continue;
+            }
+            let new_hits = |c: usize| {
+                instance.elements_of[c]
+                    .iter()
+                    .filter(|&&e| hits[e] == 0)
+                    .count()
+            };
+            let best = *instance.hit_by[e]
+                .iter()
+                .max_by(|&&a, &&b| {
+                    (new_hits(a), -costs[a])
+                        .partial_cmp(&(new_hits(b), -costs[b]))
+                        .unwrap()
+                })
+                .unwrap();
+            for &e in &instance.elements_of[best] {
+                hits[e] += 1;
+            }
+            chosen.push(best);
+        }
+        chosen.sort_by(|&a, &b| costs[b].partial_cmp(&costs[a]).unwrap());
+        chosen.retain(|&c| {
+            let redundant = instance.elements_of[c].iter().all(|&e| hits[e] > 1);
+            if redundant {
+                for &e in &instance.elements_of[c] {
+                    hits[e] -= 1;
+                }
+            }
+            !redundant
+        });
+        chosen
+    }
+
+    /// Count elements that no single candidate can hit together. Each needs its own click.
+    fn lower_bound(&self, covered: &BitSet) -> usize {
+        let mut blocked = covered.clone();
+        let mut count = 0;
+        for &e in &self.instance.by_choice {
+            if blocked.contains(e) {
+                continue;
+            }
+            count += 1;
+            for &c in &self.instance.hit_by[e] {
+                blocked.union_with(&self.instance.sets[c]);
+            }
+        }
+        count
+    }
+
+    fn search(&mut self, covered: &BitSet, chosen: &mut Vec<usize>) {
+        // A node of a big group takes long enough to check the time at every one
+        if Instant::now() > self.deadline {
+            self.timed_out = true;
+        }
+        if self.timed_out {
+            return;
+        }
+        if *covered == self.all {
+            if chosen.len() < self.best.len() {
+                self.best = chosen.clone();
+            }
+            return;
+        }
+        if chosen.len() + self.lower_bound(covered) >= self.best.len() {
+            return;
+        }
+        let instance = self.instance;
+        let element = (0..instance.elements)
+            .filter(|&e| !covered.contains(e))
+            .min_by_key(|&e| instance.hit_by[e].len())
+            .unwrap();
+        let mut choices = instance.hit_by[element].clone();
+        choices.sort_by_key(|&c| Reverse(instance.sets[c].count_new(covered)));
+        for c in choices {
+            let mut next = covered.clone();
+            next.union_with(&instance.sets[c]);
+            chosen.push(c);
+            self.search(&next, chosen);
+            chosen.pop();
+        }
+    }
+}
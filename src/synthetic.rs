@@ -0,0 +1,256 @@
+//! Module to render synthetic screenshots of the map view, with the ground truth of every target
+//!
+//! The screenshots have a noisy terrain background, pure black unexplored chunks, red spawner
+//! and worm rectangles in overlapping nests, and optionally the blue/magenta show-active-state
+//! debug circles. Targets partly hidden by unexplored chunks or the edge of the screenshot are
+//! clipped like they are in game, and are still part of the ground truth.
+
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use tiny_rng::{Rand, Rng};
+
+use crate::evaluate::Annotation;
+use crate::screen::{BoundingBox, Coord};
+use crate::solver::{solve, SolverOptions};
+use crate::{red_remote_radius, TargetClass, ARTY_REMOTE_RADIUS};
+
+/// Size in pixels of a spawner at the zoom levels that show debug circles, the same as the mask
+/// `detect` puts around a blue debug circle
+const DEBUG_SPAWNER_SIZE: (i64, i64) = (54, 43);
+/// Size in pixels of a worm at the zoom levels that show debug circles
+const DEBUG_WORM_SIZE: (i64, i64) = (24, 22);
+/// Where the first pixel of a spawner's debug circle is, from the spawner's left top corner
+const DEBUG_SPAWNER_ANCHOR: Coord = Coord { w: 30, h: 11 };
+/// Where the first pixel of a worm's debug circle is, from the worm's left top corner
+const DEBUG_WORM_ANCHOR: Coord = Coord { w: 8, h: -1 };
+/// Radius in pixels of a debug circle, small enough to fit inside the gap that
+/// `find_debug_positions` leaves between circles
+const DEBUG_CIRCLE_RADIUS: f64 = 13.;
+
+/// Terrain colours, none of which look like an enemy
+const TERRAIN: [[u8; 3]; 4] = [[72, 84, 40], [98, 90, 52], [60, 70, 44], [110, 104, 70]];
+
+/// What to put in a synthetic screenshot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneOptions {
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    /// Width in pixels of a spawner, which sets the zoom level. Ignored with debug circles,
+    /// which are only shown at a single zoom level.
+    pub spawner_width: i64,
+    /// Clusters of spawners and worms
+    pub nests: usize,
+    /// Most spawners in a nest
+    pub spawners_per_nest: usize,
+    /// Most worms in a nest
+    pub worms_per_nest: usize,
+    /// Fraction of the chunks that are unexplored
+    pub fog: f64,
+    /// Draw the show-active-state debug circles on each target
+    pub debug_circles: bool,
+}
+
+impl Default for SceneOptions {
+    fn default() -> Self {
+        SceneOptions {
+            width: 1920,
+            height: 1080,
+            seed: 0,
+            spawner_width: 20,
+            nests: 8,
+            spawners_per_nest: 8,
+            worms_per_nest: 6,
+            fog: 0.1,
+            debug_circles: false,
+        }
+    }
+}
+
+/// A synthetic screenshot and what is in it
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub img: RgbImage,
+    /// Every target with at least one visible pixel, unclipped
+    pub targets: Vec<BoundingBox>,
+    /// The class of each target in targets
+    pub classes: Vec<TargetClass>,
+    /// Artillery remote radius in pixels at the zoom level of the scene
+    pub remote_radius: u32,
+}
+
+impl Scene {
+    /// Ground truth in the format `evaluate` reads, with the exact solver's lower bound as the
+    /// minimum. The bound is the fewest shells that hit every target when the solver finishes in
+    /// its time budget, and never more than that otherwise.
+    pub fn annotation(&self, solver_options: SolverOptions) -> Annotation {
+        let solution = solve(
+            &self.targets,
+            self.remote_radius,
+            self.img.width(),
+            self.img.height(),
+            solver_options,
+        );
+        Annotation {
+            min_shells: solution.lower_bound,
+            targets: self.targets.clone(),
+            classes: self.classes.clone(),
+            region: None,
+        }
+    }
+}
+
+/// Render a map view screenshot with the targets described by options
+pub fn generate(options: &SceneOptions) -> Scene {
+    let mut rng = Rng::from_seed(options.seed);
+    let (w, h) = (options.width, options.height);
+    let (spawner_size, worm_size, remote_radius) = if options.debug_circles {
+        (DEBUG_SPAWNER_SIZE, DEBUG_WORM_SIZE, ARTY_REMOTE_RADIUS)
+    } else {
+        let spawner_w = options.spawner_width.max(4);
+        let spawner_h = (spawner_w as f64 / 1.375).round() as i64;
+        let worm = (spawner_w as f64 * 0.45).round().max(3.) as i64;
+        (
+            (spawner_w, spawner_h),
+            (worm, worm),
+            red_remote_radius(spawner_w - 1),
+        )
+    };
+
+    let mut img = RgbImage::new(w, h);
+    draw_terrain(&mut img, &mut rng);
+
+    let mut targets = vec![];
+    for _ in 0..options.nests {
+        let centre = Coord {
+            w: rng.rand_range_i64(0, w as i64),
+            h: rng.rand_range_i64(0, h as i64),
+        };
+        let spread = spawner_size.0 * 3;
+        let spawners = rng.rand_range_i64(1, options.spawners_per_nest.max(1) as i64 + 1);
+        let worms = rng.rand_range_i64(0, options.worms_per_nest as i64 + 1);
+        for i in 0..spawners + worms {
+            let (class, size) = if i < spawners {
+                (TargetClass::Spawner, spawner_size)
+            } else {
+                (TargetClass::Worm, worm_size)
+            };
+            // Worms guard the outside of the nest
+            let spread = if class == TargetClass::Worm {
+                spread * 2
+            } else {
+                spread
+            };
+            let left_top = Coord {
+                w: centre.w + rng.rand_range_i64(-spread, spread + 1) - size.0 / 2,
+                h: centre.h + rng.rand_range_i64(-spread, spread + 1) - size.1 / 2,
+            };
+            // The last pixel is inside the box, as with the boxes the detectors find
+            let bb = BoundingBox::new(left_top, size.0 - 1, size.1 - 1);
+            targets.push((bb, class, enemy_red(&mut rng)));
+        }
+    }
+    for (bb, _, color) in &targets {
+        fill_bb(&mut img, bb, *color);
+    }
+    // Unexplored chunks hide everything under them, including parts of targets
+    let chunk = (spawner_size.0 * 5) as u32;
+    let mut fog = vec![];
+    for cell_h in 0..h.div_ceil(chunk) {
+        for cell_w in 0..w.div_ceil(chunk) {
+            if rng.rand_f64() < options.fog {
+                let bb = BoundingBox::new(
+                    Coord {
+                        w: (cell_w * chunk) as i64,
+                        h: (cell_h * chunk) as i64,
+                    },
+                    chunk as i64 - 1,
+                    chunk as i64 - 1,
+                );
+                fill_bb(&mut img, &bb, Rgb([0, 0, 0]));
+                fog.push(bb);
+            }
+        }
+    }
+    let screen = BoundingBox::new(Coord::default(), w as i64 - 1, h as i64 - 1);
+    targets.retain(|(bb, _, _)| {
+        bb.collides_with_bb(&screen)
+            && bb.enumerate().any(|(w, h)| {
+                screen.collides_with_point(Coord { w, h })
+                    && !fog.iter().any(|f| f.collides_with_point(Coord { w, h }))
+            })
+    });
+    if options.debug_circles {
+        for (bb, class, _) in &targets {
+            let (anchor, color) = match class {
+                TargetClass::Spawner => (DEBUG_SPAWNER_ANCHOR, Rgb([0, 0, 0xff])),
+                _ => (DEBUG_WORM_ANCHOR, Rgb([0xff, 0, 0xff])),
+            };
+            let anchor = Coord {
+                w: bb.left_top.w + anchor.w,
+                h: bb.left_top.h + anchor.h,
+            };
+            draw_debug_circle(&mut img, anchor, color);
+        }
+    }
+
+    Scene {
+        img,
+        targets: targets.iter().map(|(bb, _, _)| *bb).collect(),
+        classes: targets.iter().map(|(_, class, _)| *class).collect(),
+        remote_radius,
+    }
+}
+
+/// Patches of terrain colour with a little noise on every pixel
+fn draw_terrain(img: &mut RgbImage, rng: &mut Rng) {
+    const PATCH: u32 = 16;
+    let cols = img.width().div_ceil(PATCH);
+    let rows = img.height().div_ceil(PATCH);
+    let patches = (0..cols * rows)
+        .map(|_| TERRAIN[rng.rand_bounded_usize(TERRAIN.len())])
+        .collect::<Vec<_>>();
+    for (x, y, px) in img.enumerate_pixels_mut() {
+        let base = patches[((y / PATCH) * cols + x / PATCH) as usize];
+        let noise = rng.rand_range_i32(-6, 7);
+        *px = Rgb(base.map(|c| (c as i32 + noise).clamp(1, 255) as u8));
+    }
+}
+
+/// A random red that the red detector sees as an enemy
+fn enemy_red(rng: &mut Rng) -> Rgb<u8> {
+    Rgb([
+        rng.rand_range_u32(180, 231) as u8,
+        rng.rand_range_u32(15, 31) as u8,
+        rng.rand_range_u32(18, 34) as u8,
+    ])
+}
+
+/// Fill the pixels of bb that are on the image
+fn fill_bb(img: &mut RgbImage, bb: &BoundingBox, color: Rgb<u8>) {
+    for (w, h) in bb.enumerate() {
+        if w >= 0 && h >= 0 && (w as u32) < img.width() && (h as u32) < img.height() {
+            img.put_pixel(w as u32, h as u32, color);
+        }
+    }
+}
+
+/// Draw a one pixel wide circle whose top row starts at anchor, where `find_debug_positions`
+/// finds it
+fn draw_debug_circle(img: &mut RgbImage, anchor: Coord, color: Rgb<u8>) {
+    let r = DEBUG_CIRCLE_RADIUS;
+    let extent = r.ceil() as i64 + 1;
+    let ring = (-extent..=extent)
+        .flat_map(|dh| (-extent..=extent).map(move |dw| (dw, dh)))
+        .filter(|&(dw, dh)| (((dw * dw + dh * dh) as f64).sqrt() - r).abs() < 0.5)
+        .collect::<Vec<_>>();
+    // Pixels are scanned row by row, so the first pixel found is the leftmost of the top row
+    let first = *ring.iter().min_by_key(|&&(dw, dh)| (dh, dw)).unwrap();
+    for (dw, dh) in ring {
+        let (w, h) = (anchor.w + dw - first.0, anchor.h + dh - first.1);
+        if w >= 0 && h >= 0 && (w as u32) < img.width() && (h as u32) < img.height() {
+            img.put_pixel(w as u32, h as u32, color);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Tests of fitting a colour classifier to picked pixels
+
+use image::{Rgb, RgbImage};
+use iron_dome::color::{fit, ColorClassifier};
+use iron_dome::legit::process_red;
+use iron_dome::{BoundingBox, Coord};
+
+const GROUND: Rgb<u8> = Rgb([60, 60, 60]);
+const FOG: Rgb<u8> = Rgb([0, 0, 0]);
+
+#[test]
+fn fit_leaves_out_the_background() {
+    let enemy = [Rgb([200, 20, 25]), Rgb([180, 25, 30])];
+    let background = [GROUND, Rgb([190, 40, 30])];
+    let classifier = fit(&enemy, &background).unwrap();
+    assert!(enemy.iter().all(|px| classifier.looks_like_enemy(px)));
+    assert!(!background.iter().any(|px| classifier.looks_like_enemy(px)));
+}
+
+#[test]
+fn fit_fails_when_the_background_is_an_enemy_colour() {
+    assert!(fit(&[Rgb([200, 20, 25])], &[Rgb([200, 20, 25])]).is_err());
+    assert!(fit(&[], &[GROUND]).is_err());
+}
+
+#[test]
+fn red_detector_finishes_with_a_classifier_fit_on_near_black_pixels() {
+    let enemy = [Rgb([3, 2, 2]), Rgb([6, 4, 3])];
+    let classifier = fit(&enemy, &[GROUND]).unwrap();
+    // The margin takes the box down to black, the colour of unexplored chunks
+    assert_eq!(
+        classifier,
+        ColorClassifier::RgbBox {
+            min: [0; 3],
+            max: [18, 16, 15],
+        }
+    );
+
+    let mut img = RgbImage::from_pixel(60, 40, GROUND);
+    let spawners = [
+        BoundingBox::new(Coord { w: 5, h: 5 }, 13, 9),
+        BoundingBox::new(Coord { w: 5, h: 25 }, 13, 9),
+    ];
+    for bb in spawners {
+        for (w, h) in bb.enumerate() {
+            img.put_pixel(w as u32, h as u32, enemy[0]);
+        }
+    }
+    for (w, h) in BoundingBox::new(Coord { w: 40, h: 0 }, 19, 39).enumerate() {
+        img.put_pixel(w as u32, h as u32, FOG);
+    }
+    let (bbs, _) = process_red(&mut img, &classifier);
+    for spawner in &spawners {
+        assert!(bbs.contains(spawner), "{:?} not in {:?}", spawner, bbs);
+    }
+}
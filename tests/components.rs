@@ -0,0 +1,206 @@
+//! Tests of the connected component red detector on small hand drawn screenshots
+
+use image::{Rgb, RgbImage};
+use iron_dome::color::ColorClassifier;
+use iron_dome::components::{classify, label, process_red_components, split_blob, unclip, Sides};
+use iron_dome::{BoundingBox, Coord, TargetClass};
+
+const GROUND: Rgb<u8> = Rgb([60, 60, 60]);
+const ENEMY: Rgb<u8> = Rgb([200, 20, 25]);
+const FOG: Rgb<u8> = Rgb([0, 0, 0]);
+
+fn bb(w: i64, h: i64, bb_w: i64, bb_h: i64) -> BoundingBox {
+    BoundingBox::new(Coord { w, h }, bb_w, bb_h)
+}
+
+/// A 30 by 20 screenshot of ground with each bb filled with its colour, later bbs on top
+fn draw(rects: &[(BoundingBox, Rgb<u8>)]) -> RgbImage {
+    let mut img = RgbImage::from_pixel(30, 20, GROUND);
+    for (bb, color) in rects {
+        for (w, h) in bb.enumerate() {
+            img.put_pixel(w as u32, h as u32, *color);
+        }
+    }
+    img
+}
+
+/// A spawner 8 pixels wide and 6 tall
+fn template() -> BoundingBox {
+    bb(0, 0, 7, 5)
+}
+
+#[test]
+fn label_measures_each_blob() {
+    let img = draw(&[(bb(2, 2, 7, 5), ENEMY), (bb(15, 10, 3, 3), ENEMY)]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 2);
+    assert_eq!(labels.blobs[0].bb, bb(2, 2, 7, 5));
+    assert_eq!(labels.blobs[0].pixels, 48);
+    assert_eq!(labels.blobs[1].bb, bb(15, 10, 3, 3));
+    assert_eq!(labels.blobs[1].pixels, 16);
+    assert!(labels.blobs.iter().all(|b| b.clipped == Sides::default()));
+    assert_eq!(labels.blob_at(9, 7), Some(0));
+    assert_eq!(labels.blob_at(18, 13), Some(1));
+    assert_eq!(labels.blob_at(10, 7), None);
+    assert_eq!(labels.blob_at(-1, 0), None);
+}
+
+#[test]
+fn label_keeps_diagonal_pixels_apart() {
+    let img = draw(&[(bb(3, 3, 0, 0), ENEMY), (bb(4, 4, 0, 0), ENEMY)]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 2);
+    assert!(labels.blobs.iter().all(|b| b.pixels == 1));
+}
+
+#[test]
+fn label_marks_sides_at_the_edge_and_fog_as_clipped() {
+    let img = draw(&[
+        (bb(0, 5, 5, 4), ENEMY),
+        (bb(10, 2, 4, 5), ENEMY),
+        (bb(15, 0, 2, 19), FOG),
+    ]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 2);
+    let left = Sides {
+        left: true,
+        ..Sides::default()
+    };
+    let right = Sides {
+        right: true,
+        ..Sides::default()
+    };
+    assert_eq!(labels.blobs[0].clipped, right);
+    assert_eq!(labels.blobs[1].clipped, left);
+}
+
+#[test]
+fn unclip_grows_a_clipped_spawner_to_full_size() {
+    // Cut off by the left edge of the screenshot, and by fog below
+    let img = draw(&[
+        (bb(0, 3, 4, 5), ENEMY),
+        (bb(15, 2, 7, 2), ENEMY),
+        (bb(10, 5, 19, 14), FOG),
+    ]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 2);
+    assert_eq!(unclip(&labels.blobs[0], &template()), bb(15, 2, 7, 5));
+    assert_eq!(unclip(&labels.blobs[1], &template()), bb(-3, 3, 7, 5));
+}
+
+#[test]
+fn unclip_leaves_a_blob_that_is_not_a_rectangle() {
+    // An L cut off by the left edge of the screenshot
+    let img = draw(&[(bb(0, 3, 1, 5), ENEMY), (bb(0, 7, 4, 1), ENEMY)]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 1);
+    let blob = labels.blobs[0];
+    assert!(blob.clipped.left);
+    assert_eq!(unclip(&blob, &template()), blob.bb);
+}
+
+#[test]
+fn split_blob_splits_touching_spawners() {
+    let img = draw(&[(bb(2, 2, 7, 5), ENEMY), (bb(10, 4, 7, 5), ENEMY)]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 1);
+    let corners = labels.left_top_corners();
+    assert_eq!(corners[0], vec![Coord { w: 2, h: 2 }]);
+    assert_eq!(
+        split_blob(&labels, 0, &corners[0], &template()),
+        vec![bb(2, 2, 7, 5), bb(10, 4, 7, 5)]
+    );
+}
+
+#[test]
+fn split_blob_keeps_a_worm_next_to_a_spawner_worm_sized() {
+    let img = draw(&[(bb(2, 2, 7, 5), ENEMY), (bb(10, 3, 3, 3), ENEMY)]);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 1);
+    let corners = labels.left_top_corners();
+    assert_eq!(
+        split_blob(&labels, 0, &corners[0], &template()),
+        vec![bb(2, 2, 7, 5), bb(10, 3, 3, 3)]
+    );
+}
+
+#[test]
+fn split_blob_merges_slivers_into_a_neighbour() {
+    // A spawner with a 2 by 2 bump, too small to be a target of its own
+    let img = draw(&[(bb(2, 2, 7, 5), ENEMY), (bb(10, 4, 1, 1), ENEMY)]);
+    let labels = label(&img, &ColorClassifier::default());
+    let corners = labels.left_top_corners();
+    assert_eq!(
+        split_blob(&labels, 0, &corners[0], &template()),
+        vec![bb(2, 2, 9, 5)]
+    );
+}
+
+#[test]
+fn split_blob_cuts_no_more_pieces_than_the_pixels_fill() {
+    // A comb of thin teeth on a bar, which spawner sized boxes cut into more pieces than the
+    // comb has pixels to fill
+    let mut comb = vec![(bb(2, 12, 23, 0), ENEMY)];
+    comb.extend((0..8).map(|i| (bb(2 + 3 * i, 2, 1, 9), ENEMY)));
+    let img = draw(&comb);
+    let labels = label(&img, &ColorClassifier::default());
+    assert_eq!(labels.blobs.len(), 1);
+    let pixels = labels.blobs[0].pixels;
+    let corners = labels.left_top_corners();
+    let pieces = split_blob(&labels, 0, &corners[0], &template());
+    let needed = pieces
+        .iter()
+        .map(|bb| bb.pixel_count().min(template().pixel_count()) as f64 * 0.8)
+        .sum::<f64>();
+    assert!(needed <= pixels as f64, "{:?}", pieces);
+    for (w, h) in labels.blobs[0].bb.enumerate() {
+        if labels.blob_at(w, h) == Some(0) {
+            let point = Coord { w, h };
+            assert!(pieces.iter().any(|bb| bb.collides_with_point(point)));
+        }
+    }
+}
+
+#[test]
+fn classify_tells_spawners_from_narrower_worms() {
+    assert_eq!(classify(&bb(0, 0, 7, 5), &template()), TargetClass::Spawner);
+    assert_eq!(
+        classify(&bb(0, 0, 14, 5), &template()),
+        TargetClass::Spawner
+    );
+    assert_eq!(classify(&bb(0, 0, 4, 5), &template()), TargetClass::Worm);
+    assert_eq!(
+        classify(&bb(0, 0, 4, 5), &BoundingBox::default()),
+        TargetClass::Unknown
+    );
+}
+
+#[test]
+fn process_red_components_classifies_each_box() {
+    let img = draw(&[
+        (bb(2, 2, 7, 5), ENEMY),
+        (bb(15, 2, 7, 5), ENEMY),
+        (bb(2, 12, 7, 5), ENEMY),
+        (bb(15, 12, 4, 4), ENEMY),
+    ]);
+    let (bbs, classes, spawner_width) = process_red_components(&img, &ColorClassifier::default());
+    assert_eq!(spawner_width, 7);
+    assert_eq!(
+        bbs,
+        vec![
+            bb(2, 2, 7, 5),
+            bb(15, 2, 7, 5),
+            bb(2, 12, 7, 5),
+            bb(15, 12, 4, 4)
+        ]
+    );
+    assert_eq!(
+        classes,
+        vec![
+            TargetClass::Spawner,
+            TargetClass::Spawner,
+            TargetClass::Spawner,
+            TargetClass::Worm
+        ]
+    );
+}
@@ -0,0 +1,93 @@
+//! Property tests of the pixel geometry in `screen.rs`, checked against brute force references
+
+use iron_dome::screen::BoxGrid;
+use iron_dome::{BoundingBox, Coord};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// A valid box of up to 20 by 20 pixels near the origin
+fn bb() -> impl Strategy<Value = BoundingBox> {
+    (-50i64..50, -50i64..50, 0i64..20, 0i64..20)
+        .prop_map(|(w, h, bw, bh)| BoundingBox::new(Coord { w, h }, bw, bh))
+}
+
+fn coord() -> impl Strategy<Value = Coord> {
+    (-100i64..100, -100i64..100).prop_map(|(w, h)| Coord { w, h })
+}
+
+/// Whether any pixel of bb is at most radius from pos, checking every pixel
+fn reference_collides_with_circle(bb: &BoundingBox, pos: Coord, radius: u32) -> bool {
+    bb.enumerate().any(|(w, h)| {
+        let (dw, dh) = (w - pos.w, h - pos.h);
+        dw * dw + dh * dh <= (radius as i64).pow(2)
+    })
+}
+
+proptest! {
+    #[test]
+    fn new_round_trips(left_top in coord(), w in 0i64..100, h in 0i64..100) {
+        let bb = BoundingBox::new(left_top, w, h);
+        prop_assert_eq!(bb.w(), w);
+        prop_assert_eq!(bb.h(), h);
+        prop_assert_eq!(BoundingBox::new(bb.left_top, bb.w(), bb.h()), bb);
+    }
+
+    #[test]
+    fn enumerate_covers_inclusive_bounds(bb in bb()) {
+        let pixels = bb.enumerate().collect::<Vec<_>>();
+        prop_assert_eq!(pixels.len() as i64, bb.pixel_count());
+        prop_assert_eq!(bb.pixel_count(), (bb.w() + 1) * (bb.h() + 1));
+        prop_assert_eq!(bb.area(), bb.w() * bb.h());
+        prop_assert_eq!(pixels.first(), Some(&(bb.left_top.w, bb.left_top.h)));
+        prop_assert_eq!(pixels.last(), Some(&(bb.right_bottom.w, bb.right_bottom.h)));
+        for (w, h) in pixels {
+            let pixel = Coord { w, h };
+            prop_assert!(bb.collides_with_point(pixel));
+        }
+    }
+
+    #[test]
+    fn collides_with_point_matches_enumerate(bb in bb(), pos in coord()) {
+        let inside = bb.enumerate().any(|(w, h)| Coord { w, h } == pos);
+        prop_assert_eq!(bb.collides_with_point(pos), inside);
+    }
+
+    #[test]
+    fn collides_with_bb_matches_shared_pixels(a in bb(), b in bb()) {
+        let shared = a.enumerate().any(|(w, h)| b.collides_with_point(Coord { w, h }));
+        prop_assert_eq!(a.collides_with_bb(&b), shared);
+        prop_assert_eq!(b.collides_with_bb(&a), shared);
+    }
+
+    #[test]
+    fn collides_with_circle_matches_reference(bb in bb(), pos in coord(), radius in 0u32..60) {
+        prop_assert_eq!(
+            bb.collides_with_circle(pos, radius),
+            reference_collides_with_circle(&bb, pos, radius)
+        );
+    }
+
+    #[test]
+    fn fast_calc_never_rejects_a_hit(bb in bb(), pos in coord(), radius in 0u32..60) {
+        if bb.collides_with_circle(pos, radius) {
+            prop_assert!(bb.collides_fast_calc(pos, radius as i64));
+        }
+    }
+
+    #[test]
+    fn grid_query_matches_linear_scan(
+        bbs in vec(bb(), 0..40),
+        pos in coord(),
+        radius in 0u32..60,
+        cell_size in 0i64..80,
+    ) {
+        let grid = BoxGrid::new(&bbs, cell_size);
+        let mut found = vec![];
+        grid.query_circle(pos, radius, |i| found.push(i));
+        found.sort_unstable();
+        let expected = (0..bbs.len())
+            .filter(|&i| bbs[i].collides_with_circle(pos, radius))
+            .collect::<Vec<_>>();
+        prop_assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,244 @@
+//! Golden image regression tests for the detectors
+//!
+//! Runs the debug detector and both red detectors on `hard.png` and every image in `zoom/`, plans clicks for
+//! what they find, and compares the bounding boxes and click count against the golden file of
+//! the image in `tests/golden/`. Small differences are allowed, see the tolerances below.
+//!
+//! ``` cargo test --test golden ```
+//!
+//! After an intended change to the detectors, rewrite the golden files with
+//!
+//! ``` cargo test --test golden -- --bless ```
+
+use image::RgbImage;
+use iron_dome::{
+    detect, plan, BoundingBox, Coord, DetectOptions, Generator, PlanOptions, RedDetector,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Pixels each side of a bounding box may move and still match its golden bounding box
+const BB_TOLERANCE_PX: i64 = 2;
+/// Fraction of the bounding boxes that may have no match
+const UNMATCHED_TOLERANCE: f64 = 0.01;
+/// Fraction by which the click count may differ from the golden click count
+const CLICK_TOLERANCE: f64 = 0.05;
+
+/// What one detector found in an image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Golden {
+    /// [left, top, right, bottom] of each bounding box
+    bbs: Vec<[i64; 4]>,
+    clicks: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct GoldenImage {
+    debug: Golden,
+    red: Golden,
+    components: Golden,
+}
+
+fn main() {
+    let bless = std::env::args().any(|arg| arg == "--bless");
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    let mut images = vec![root.join("hard.png")];
+    let mut zoom = std::fs::read_dir(root.join("zoom"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect::<Vec<_>>();
+    zoom.sort();
+    images.extend(zoom);
+
+    let mut failures = vec![];
+    for path in &images {
+        let name = path.strip_prefix(root).unwrap().display().to_string();
+        let golden_path = golden_path(root, &name);
+        let img = image::open(path).unwrap().to_rgb8();
+        let found = GoldenImage {
+            debug: run_detector(&img, true, RedDetector::Scan),
+            red: run_detector(&img, false, RedDetector::Scan),
+            components: run_detector(&img, false, RedDetector::Components),
+        };
+
+        if bless {
+            std::fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            std::fs::write(&golden_path, to_json(&found)).unwrap();
+            println!("blessed {}", golden_path.display());
+            continue;
+        }
+        let golden = match std::fs::read_to_string(&golden_path) {
+            Ok(text) => serde_json::from_str::<GoldenImage>(&text).unwrap(),
+            Err(e) => {
+                failures.push(format!(
+                    "{}: could not read {}: {}, run with --bless to create it",
+                    name,
+                    golden_path.display(),
+                    e
+                ));
+                continue;
+            }
+        };
+        let mut errors = vec![];
+        compare("debug", &golden.debug, &found.debug, &mut errors);
+        compare("red", &golden.red, &found.red, &mut errors);
+        compare(
+            "components",
+            &golden.components,
+            &found.components,
+            &mut errors,
+        );
+        if errors.is_empty() {
+            println!("test {} ... ok", name);
+        } else {
+            println!("test {} ... FAILED", name);
+            failures.extend(errors.into_iter().map(|e| format!("{}: {}", name, e)));
+        }
+    }
+
+    if !failures.is_empty() {
+        for failure in &failures {
+            println!("{}", failure);
+        }
+        println!(
+            "\nIf these changes are intended, run cargo test --test golden -- --bless to update the golden files"
+        );
+        std::process::exit(1);
+    }
+}
+
+/// tests/golden/<image path with / replaced by _>.json
+fn golden_path(root: &Path, image: &str) -> PathBuf {
+    let name = Path::new(image).with_extension("json");
+    let name = name.to_string_lossy().replace(['/', '\\'], "_");
+    root.join("tests").join("golden").join(name)
+}
+
+/// Run only the debug or only the given red detector, and plan clicks with the fixed generator
+/// so the click count doesn't depend on random guesses
+fn run_detector(img: &RgbImage, scan_debug: bool, red_detector: RedDetector) -> Golden {
+    let detections = detect(
+        img,
+        DetectOptions {
+            scan_debug,
+            scan_red: !scan_debug,
+            red_detector,
+            ..DetectOptions::default()
+        },
+    );
+    let clicks = plan(
+        &detections.bbs,
+        PlanOptions {
+            generator: Generator::Fixed,
+            ..PlanOptions::for_detections(&detections, img)
+        },
+    );
+    Golden {
+        bbs: detections
+            .bbs
+            .iter()
+            .map(|bb| {
+                [
+                    bb.left_top.w,
+                    bb.left_top.h,
+                    bb.right_bottom.w,
+                    bb.right_bottom.h,
+                ]
+            })
+            .collect(),
+        clicks: clicks.len(),
+    }
+}
+
+/// Add a message to errors for each way found is further from golden than the tolerances allow
+fn compare(detector: &str, golden: &Golden, found: &Golden, errors: &mut Vec<String>) {
+    let missing = unmatched(&golden.bbs, &found.bbs);
+    let extra = unmatched(&found.bbs, &golden.bbs);
+    let allowed = (golden.bbs.len().max(found.bbs.len()) as f64 * UNMATCHED_TOLERANCE) as usize;
+    if missing.len() > allowed || extra.len() > allowed {
+        let mut message = format!(
+            "{} detector found {} bbs instead of {}, {} golden bbs missing and {} extra (at most {} allowed)",
+            detector,
+            found.bbs.len(),
+            golden.bbs.len(),
+            missing.len(),
+            extra.len(),
+            allowed
+        );
+        for bb in missing.iter().take(5) {
+            write!(message, "\n    missing {:?}", to_bb(bb)).unwrap();
+        }
+        for bb in extra.iter().take(5) {
+            write!(message, "\n    extra {:?}", to_bb(bb)).unwrap();
+        }
+        errors.push(message);
+    }
+
+    let allowed = (golden.clicks as f64 * CLICK_TOLERANCE).ceil() as usize;
+    if found.clicks.abs_diff(golden.clicks) > allowed {
+        errors.push(format!(
+            "{} detector generated {} clicks instead of {} (at most {} different allowed)",
+            detector, found.clicks, golden.clicks, allowed
+        ));
+    }
+}
+
+/// The bbs in from that no bb in to matches within BB_TOLERANCE_PX
+fn unmatched(from: &[[i64; 4]], to: &[[i64; 4]]) -> Vec<[i64; 4]> {
+    let exact = to.iter().collect::<HashSet<_>>();
+    from.iter()
+        .filter(|bb| !exact.contains(bb))
+        .filter(|bb| {
+            !to.iter().any(|other| {
+                bb.iter()
+                    .zip(other)
+                    .all(|(a, b)| (a - b).abs() <= BB_TOLERANCE_PX)
+            })
+        })
+        .copied()
+        .collect()
+}
+
+fn to_bb(bb: &[i64; 4]) -> BoundingBox {
+    BoundingBox {
+        left_top: Coord { w: bb[0], h: bb[1] },
+        right_bottom: Coord { w: bb[2], h: bb[3] },
+    }
+}
+
+/// Golden file contents, with one bb per line so changes to the golden files are easy to review
+fn to_json(golden: &GoldenImage) -> String {
+    let mut json = "{\n".to_owned();
+    let detectors = [
+        ("debug", &golden.debug),
+        ("red", &golden.red),
+        ("components", &golden.components),
+    ];
+    for (i, &(detector, golden)) in detectors.iter().enumerate() {
+        let bbs = if golden.bbs.is_empty() {
+            "[]".to_owned()
+        } else {
+            let lines = golden
+                .bbs
+                .iter()
+                .map(|bb| format!("      {}", serde_json::to_string(bb).unwrap()))
+                .collect::<Vec<_>>();
+            format!("[\n{}\n    ]", lines.join(",\n"))
+        };
+        write!(
+            json,
+            "  \"{}\": {{\n    \"clicks\": {},\n    \"bbs\": {}\n  }}{}\n",
+            detector,
+            golden.clicks,
+            bbs,
+            if i + 1 < detectors.len() { "," } else { "" }
+        )
+        .unwrap();
+    }
+    json.push_str("}\n");
+    json
+}
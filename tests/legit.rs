@@ -0,0 +1,70 @@
+//! Tests that the scan red detector finishes with any classifier the config can hold
+
+use image::{Rgb, RgbImage};
+use iron_dome::color::ColorClassifier;
+use iron_dome::legit::process_red;
+use iron_dome::{BoundingBox, Coord};
+
+const GROUND: Rgb<u8> = Rgb([60, 60, 60]);
+const ENEMY: Rgb<u8> = Rgb([200, 20, 25]);
+
+/// A 60 by 40 screenshot of ground with two spawners and a worm
+fn screenshot() -> RgbImage {
+    let mut img = RgbImage::from_pixel(60, 40, GROUND);
+    for bb in [
+        BoundingBox::new(Coord { w: 5, h: 5 }, 13, 9),
+        BoundingBox::new(Coord { w: 30, h: 20 }, 13, 9),
+        BoundingBox::new(Coord { w: 50, h: 5 }, 5, 5),
+    ] {
+        for (w, h) in bb.enumerate() {
+            img.put_pixel(w as u32, h as u32, ENEMY);
+        }
+    }
+    img
+}
+
+fn in_image(bb: &BoundingBox, img: &RgbImage) -> bool {
+    bb.left_top.w >= 0
+        && bb.left_top.h >= 0
+        && bb.right_bottom.w < img.width() as i64
+        && bb.right_bottom.h < img.height() as i64
+}
+
+#[test]
+fn finishes_when_every_colour_is_an_enemy() {
+    let img = screenshot();
+    let classifier = ColorClassifier::RgbBox {
+        min: [0; 3],
+        max: [255; 3],
+    };
+    let (bbs, _) = process_red(&mut img.clone(), &classifier);
+    assert!(!bbs.is_empty());
+    assert!(bbs.iter().all(|bb| in_image(bb, &img)), "{:?}", bbs);
+}
+
+#[test]
+fn finishes_when_black_is_an_enemy() {
+    let img = screenshot();
+    let classifier = ColorClassifier::Hsv {
+        hue: [0., 360.],
+        saturation: [0., 1.],
+        value: [0., 0.3],
+    };
+    let (bbs, _) = process_red(&mut img.clone(), &classifier);
+    assert!(bbs.iter().all(|bb| in_image(bb, &img)), "{:?}", bbs);
+}
+
+#[test]
+fn finds_each_target_once_when_white_is_an_enemy() {
+    let img = screenshot();
+    let classifier = ColorClassifier::RgbBox {
+        min: [150, 0, 0],
+        max: [255; 3],
+    };
+    let found = process_red(&mut img.clone(), &classifier);
+    assert_eq!(
+        found,
+        process_red(&mut img.clone(), &ColorClassifier::default())
+    );
+    assert_eq!(found.0.len(), 3);
+}
@@ -0,0 +1,41 @@
+//! Tests of the per-image reports written with --output
+
+use iron_dome::report::{Report, Target};
+use iron_dome::{BoundingBox, Coord, Detector, TargetClass};
+
+fn report(image: &str) -> Report {
+    Report {
+        image: image.to_string(),
+        detector: Detector::Red,
+        spawner_width: Some(13),
+        remote_radius: 20,
+        targets: vec![Target {
+            class: TargetClass::Spawner,
+            bb: BoundingBox::new(Coord { w: 5, h: 6 }, 13, 9),
+        }],
+        clicks: vec![Coord { w: 11, h: 10 }],
+    }
+}
+
+#[test]
+fn to_csv_writes_a_row_per_target_and_click() {
+    assert_eq!(
+        report("shots/a.png").to_csv(),
+        "image,detector,spawner_width,remote_radius,kind,class,w,h,right_bottom_w,right_bottom_h\n\
+         shots/a.png,red,13,20,target,spawner,5,6,18,15\n\
+         shots/a.png,red,13,20,click,,11,10,,\n"
+    );
+}
+
+#[test]
+fn to_csv_quotes_an_image_path_with_commas_and_quotes() {
+    let csv = report("shots/base \"north\", day 2.png").to_csv();
+    let rows = csv.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            "\"shots/base \"\"north\"\", day 2.png\",red,13,20,target,spawner,5,6,18,15",
+            "\"shots/base \"\"north\"\", day 2.png\",red,13,20,click,,11,10,,",
+        ]
+    );
+}
@@ -0,0 +1,92 @@
+//! Property tests of the exact click solver, checked against brute force on tiny images
+
+use iron_dome::solver::{solve, SolverOptions};
+use iron_dome::{BoundingBox, Coord};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// Width and height of the tiny images, small enough to try every click
+const SIZE: i64 = 24;
+
+/// A box of up to 5 by 5 pixels, which may hang off the edge of the image
+fn bb() -> impl Strategy<Value = BoundingBox> {
+    (-3i64..SIZE, -3i64..SIZE, 0i64..5, 0i64..5)
+        .prop_map(|(w, h, bw, bh)| BoundingBox::new(Coord { w, h }, bw, bh))
+}
+
+/// The bbs each pixel of the image hits, as a bit mask
+fn hit_masks(bbs: &[BoundingBox], radius: u32) -> Vec<u32> {
+    let mut masks = vec![];
+    for h in 0..SIZE {
+        for w in 0..SIZE {
+            let click = Coord { w, h };
+            let mask = (0..bbs.len())
+                .filter(|&i| bbs[i].collides_with_circle(click, radius))
+                .fold(0, |mask, i| mask | 1 << i);
+            masks.push(mask);
+        }
+    }
+    masks.sort_unstable();
+    masks.dedup();
+    masks
+}
+
+/// Fewest clicks in the image that hit every bb some click can hit, by breadth first search over
+/// the sets of bbs hit so far
+fn brute_force_optimum(masks: &[u32], reachable: u32) -> usize {
+    let mut clicks = vec![usize::MAX; 1 << 8];
+    clicks[0] = 0;
+    let mut frontier = vec![0u32];
+    while clicks[reachable as usize] == usize::MAX {
+        let mut next = vec![];
+        for &hit in &frontier {
+            for &mask in masks {
+                let more = (hit | mask) as usize;
+                if clicks[more] == usize::MAX {
+                    clicks[more] = clicks[hit as usize] + 1;
+                    next.push(more as u32);
+                }
+            }
+        }
+        frontier = next;
+    }
+    clicks[reachable as usize]
+}
+
+proptest! {
+    #[test]
+    fn exact_matches_brute_force(bbs in vec(bb(), 1..8), radius in 0u32..7) {
+        let masks = hit_masks(&bbs, radius);
+        let reachable = masks.iter().fold(0, |all, mask| all | mask);
+        let optimum = brute_force_optimum(&masks, reachable);
+
+        let solution = solve(&bbs, radius, SIZE as u32, SIZE as u32, SolverOptions::default());
+        for click in &solution.clicks {
+            prop_assert!((0..SIZE).contains(&click.w) && (0..SIZE).contains(&click.h));
+        }
+        for (i, bb) in bbs.iter().enumerate() {
+            if reachable & 1 << i != 0 {
+                prop_assert!(solution.clicks.iter().any(|click| bb.collides_with_circle(*click, radius)));
+            }
+        }
+        prop_assert_eq!(solution.clicks.len(), optimum);
+        prop_assert!(solution.lower_bound <= solution.clicks.len());
+        prop_assert!(solution.optimal());
+    }
+
+    #[test]
+    fn out_of_time_still_hits_everything(bbs in vec(bb(), 1..8), radius in 0u32..7) {
+        let masks = hit_masks(&bbs, radius);
+        let reachable = masks.iter().fold(0, |all, mask| all | mask);
+        let optimum = brute_force_optimum(&masks, reachable);
+
+        let solution = solve(&bbs, radius, SIZE as u32, SIZE as u32, SolverOptions { time_budget_ms: 0 });
+        for (i, bb) in bbs.iter().enumerate() {
+            if reachable & 1 << i != 0 {
+                prop_assert!(solution.clicks.iter().any(|click| bb.collides_with_circle(*click, radius)));
+            }
+        }
+        prop_assert!(solution.lower_bound <= optimum);
+        prop_assert!(optimum <= solution.clicks.len());
+    }
+}
@@ -0,0 +1,63 @@
+//! Checks that the detectors find the targets of synthetic screenshots on fixed seeds
+
+use iron_dome::synthetic::{generate, SceneOptions};
+use iron_dome::{detect, DetectOptions, Detector};
+
+/// Seeds of the screenshots each detector is run on
+const SEEDS: std::ops::Range<u64> = 0..8;
+
+/// Targets, targets that no detection overlaps, detections, and detections that overlap no target,
+/// summed over SEEDS
+fn score(debug_circles: bool, detector: Detector) -> (usize, usize, usize, usize) {
+    let (mut targets, mut missed, mut detected, mut false_positives) = (0, 0, 0, 0);
+    for seed in SEEDS {
+        let scene = generate(&SceneOptions {
+            seed,
+            debug_circles,
+            ..SceneOptions::default()
+        });
+        let detections = detect(&scene.img, DetectOptions::default());
+        assert_eq!(detections.detector, detector, "seed {}", seed);
+        targets += scene.targets.len();
+        missed += scene
+            .targets
+            .iter()
+            .filter(|t| !detections.bbs.iter().any(|bb| t.collides_with_bb(bb)))
+            .count();
+        detected += detections.bbs.len();
+        false_positives += detections
+            .bbs
+            .iter()
+            .filter(|bb| !scene.targets.iter().any(|t| t.collides_with_bb(bb)))
+            .count();
+    }
+    (targets, missed, detected, false_positives)
+}
+
+// Targets almost hidden by unexplored chunks or the edge of the screen may be missed, and a
+// debug circle can be off screen while part of its target is on it, so neither detector is
+// expected to find every target
+
+#[test]
+fn red_detector_finds_the_targets() {
+    let (targets, missed, detected, false_positives) = score(false, Detector::Red);
+    assert!(missed * 20 <= targets, "missed {} of {}", missed, targets);
+    assert!(
+        false_positives * 50 <= detected,
+        "{} of {} detections are not targets",
+        false_positives,
+        detected
+    );
+}
+
+#[test]
+fn debug_detector_finds_the_targets() {
+    let (targets, missed, detected, false_positives) = score(true, Detector::Debug);
+    assert!(missed * 20 <= targets, "missed {} of {}", missed, targets);
+    assert!(
+        false_positives * 50 <= detected,
+        "{} of {} detections are not targets",
+        false_positives,
+        detected
+    );
+}